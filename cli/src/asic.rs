@@ -0,0 +1,304 @@
+//! ASiC-E (Associated Signature Containers, extended form) support to sign multiple files at once.
+//! The container holds a manifest that lists the SHA-256 digest of each file,
+//! and a CMS detached signature of the manifest.
+//! The signature is plain CMS without the signing certificate attribute, so it is not CAdES.
+//! Refer ETSI EN 319 162-1 for details of the format.
+
+use std::io::{Read, Seek, Write};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use x509_cert::Certificate;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{cms, digest};
+
+const MIMETYPE_PATH: &str = "mimetype";
+const MIMETYPE: &str = "application/vnd.etsi.asic-e+zip";
+const MANIFEST_PATH: &str = "META-INF/ASiCManifest.xml";
+const SIGNATURE_PATH: &str = "META-INF/signature.p7s";
+const SHA256_URI: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("I/O error occurred: {0}")]
+    IO(#[from] std::io::Error),
+
+    #[error("ZIP error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("Base64 decoding failed: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("Signature error: {0}")]
+    Cms(#[from] cms::Error),
+
+    #[error("The container is not an ASiC-E container")]
+    NotAsic,
+
+    #[error("The file is listed twice: {0}")]
+    DuplicateFile(String),
+
+    #[error("The file is not covered by the manifest: {0}")]
+    UnsignedFile(String),
+
+    #[error("The digest does not match: {0}")]
+    DigestMismatch(String),
+}
+
+/// A file to be stored in the container.
+pub struct Entry {
+    pub name: String,
+    pub content: Vec<u8>,
+}
+
+/// A reference to a signed file, listed in the manifest.
+struct Reference {
+    uri: String,
+    algorithm: String,
+    digest: Vec<u8>,
+}
+
+/// Creates an ASiC-E container of the files.
+/// The first certificate in the chain must be the one of the signer,
+/// and `sign` is called with a DigestInfo to be signed by the corresponding key.
+pub fn create<W, F, E>(
+    writer: W,
+    entries: &[Entry],
+    chain: &[Certificate],
+    sign: F,
+) -> Result<(), E>
+where
+    W: Write + Seek,
+    F: FnOnce(Vec<u8>) -> Result<Vec<u8>, E>,
+    E: From<Error> + From<cms::Error>,
+{
+    let mut references = Vec::<Reference>::new();
+    for entry in entries {
+        if references.iter().any(|r| r.uri == entry.name) || entry.name == MIMETYPE_PATH {
+            return Err(Error::DuplicateFile(entry.name.clone()).into());
+        }
+
+        references.push(Reference {
+            uri: entry.name.clone(),
+            algorithm: SHA256_URI.to_string(),
+            digest: digest::sha256(&entry.content),
+        });
+    }
+
+    let manifest = manifest(&references).into_bytes();
+    let signature = cms::sign(chain, &manifest, sign)?;
+
+    write(writer, entries, &manifest, &signature).map_err(E::from)
+}
+
+/// Verifies the ASiC-E container.
+/// Returns the names of signed files and the certificate of the signer if all files are
+/// covered by the valid signature, and the certificate is anchored to any of the trusted CAs.
+pub fn verify<R>(reader: R, anchors: &[Certificate]) -> Result<(Vec<String>, Certificate), Error>
+where
+    R: Read + Seek,
+{
+    let mut archive = ZipArchive::new(reader)?;
+
+    if read_entry(&mut archive, MIMETYPE_PATH)? != MIMETYPE.as_bytes() {
+        return Err(Error::NotAsic);
+    }
+
+    let manifest = read_entry(&mut archive, MANIFEST_PATH)?;
+    let signature = read_entry(&mut archive, SIGNATURE_PATH)?;
+
+    let signer = cms::verify(&signature, &manifest)?;
+    cms::verify_chain(&signature, &signer, anchors)?;
+
+    let references = parse_manifest(&String::from_utf8_lossy(&manifest))?;
+    for reference in &references {
+        let content = read_entry(&mut archive, &reference.uri)?;
+        if reference.algorithm != SHA256_URI || reference.digest != digest::sha256(&content) {
+            return Err(Error::DigestMismatch(reference.uri.clone()));
+        }
+    }
+
+    let names = archive.file_names().map(str::to_string).collect::<Vec<_>>();
+
+    for name in &names {
+        let is_data_object = name != MIMETYPE_PATH && !name.starts_with("META-INF/");
+        if is_data_object && !references.iter().any(|r| &r.uri == name) {
+            return Err(Error::UnsignedFile(name.clone()));
+        }
+    }
+
+    Ok((references.into_iter().map(|r| r.uri).collect(), signer))
+}
+
+fn write<W>(writer: W, entries: &[Entry], manifest: &[u8], signature: &[u8]) -> Result<(), Error>
+where
+    W: Write + Seek,
+{
+    let mut zip = ZipWriter::new(writer);
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // The mimetype must be the first entry, without compression.
+    zip.start_file(MIMETYPE_PATH, stored)?;
+    zip.write_all(MIMETYPE.as_bytes())?;
+
+    for entry in entries {
+        zip.start_file(&entry.name, deflated)?;
+        zip.write_all(&entry.content)?;
+    }
+
+    zip.start_file(MANIFEST_PATH, deflated)?;
+    zip.write_all(manifest)?;
+    zip.start_file(SIGNATURE_PATH, deflated)?;
+    zip.write_all(signature)?;
+    zip.finish()?;
+
+    Ok(())
+}
+
+fn read_entry<R>(archive: &mut ZipArchive<R>, name: &str) -> Result<Vec<u8>, Error>
+where
+    R: Read + Seek,
+{
+    let mut buf = Vec::new();
+    archive.by_name(name)?.read_to_end(&mut buf)?;
+
+    Ok(buf)
+}
+
+fn manifest(references: &[Reference]) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#,
+        "\n",
+        r#"<asic:ASiCManifest xmlns:asic="http://uri.etsi.org/02918/v1.2.1#" xmlns:ds="http://www.w3.org/2000/09/xmldsig#">"#,
+        "\n",
+    ));
+
+    xml.push_str(&format!(
+        "  <asic:SigReference URI=\"{SIGNATURE_PATH}\" MimeType=\"application/pkcs7-signature\"/>\n"
+    ));
+
+    for reference in references {
+        xml.push_str(&format!(
+            concat!(
+                "  <asic:DataObjectReference URI=\"{}\" MimeType=\"application/octet-stream\">\n",
+                "    <ds:DigestMethod Algorithm=\"{}\"/>\n",
+                "    <ds:DigestValue>{}</ds:DigestValue>\n",
+                "  </asic:DataObjectReference>\n",
+            ),
+            escape(&reference.uri),
+            reference.algorithm,
+            BASE64.encode(&reference.digest),
+        ));
+    }
+
+    xml.push_str("</asic:ASiCManifest>\n");
+    xml
+}
+
+/// Extracts the references from the manifest.
+/// This is not a complete XML parser, but enough for manifests made by common implementations.
+fn parse_manifest(xml: &str) -> Result<Vec<Reference>, Error> {
+    let mut references = Vec::new();
+    let mut current: Option<Reference> = None;
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        let end = rest[start..].find('>').ok_or(Error::NotAsic)? + start;
+        let tag = &rest[start + 1..end];
+        let text = &rest[end + 1..];
+        let text = &text[..text.find('<').unwrap_or(text.len())];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split_whitespace()
+            .next()
+            .unwrap_or_default();
+        let local_name = name.rsplit(':').next().unwrap_or_default();
+
+        match (local_name, closing, current.as_mut()) {
+            ("DataObjectReference", false, _) => {
+                current = Some(Reference {
+                    uri: unescape(&attribute(tag, "URI").ok_or(Error::NotAsic)?),
+                    algorithm: String::new(),
+                    digest: vec![],
+                });
+            }
+            ("DataObjectReference", true, Some(_)) => {
+                references.extend(current.take());
+            }
+            ("DigestMethod", false, Some(r)) => {
+                r.algorithm = attribute(tag, "Algorithm").unwrap_or_default();
+            }
+            ("DigestValue", false, Some(r)) => {
+                r.digest = BASE64.decode(text.trim())?;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(references)
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!("{name}=");
+    let pos = tag
+        .match_indices(&pattern)
+        .map(|(pos, _)| pos)
+        .find(|pos| *pos > 0 && tag.as_bytes()[pos - 1].is_ascii_whitespace())?;
+
+    let value = &tag[pos + pattern.len()..];
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &value[quote.len_utf8()..];
+
+    Some(value[..value.find(quote)?].to_string())
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&gt;", ">")
+        .replace("&lt;", "<")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let references = vec![Reference {
+            uri: "a&b.txt".to_string(),
+            algorithm: SHA256_URI.to_string(),
+            digest: digest::sha256(b"Hello"),
+        }];
+
+        let parsed = parse_manifest(&manifest(&references)).unwrap();
+
+        assert_eq!(1, parsed.len());
+        assert_eq!("a&b.txt", parsed[0].uri);
+        assert_eq!(SHA256_URI, parsed[0].algorithm);
+        assert_eq!(digest::sha256(b"Hello"), parsed[0].digest);
+    }
+
+    #[test]
+    fn test_attribute() {
+        assert_eq!(
+            Some("a.txt".to_string()),
+            attribute("<ref URI='a.txt' Type=\"x\"", "URI"),
+        );
+        assert_eq!(None, attribute("<ref URI=あいう\"", "URI"));
+        assert_eq!(None, attribute("<ref URI=a.txt", "URI"));
+    }
+}
@@ -0,0 +1,339 @@
+//! CMS (Cryptographic Message Syntax) support to make detached signatures using the card.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cms::cert::{CertificateChoices, IssuerAndSerialNumber};
+use cms::content_info::{CmsVersion, ContentInfo};
+use cms::signed_data::{
+    CertificateSet, EncapsulatedContentInfo, SignedData, SignerIdentifier, SignerInfo, SignerInfos,
+};
+use der::asn1::{ObjectIdentifier, OctetString, SetOfVec, UtcTime};
+use der::{Any, DateTime, Decode, Encode};
+use spki::AlgorithmIdentifierOwned;
+use x509_cert::attr::Attribute;
+use x509_cert::Certificate;
+
+use crate::digest;
+
+const ID_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.1");
+const ID_SIGNED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.2");
+const ID_CONTENT_TYPE: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.3");
+const ID_MESSAGE_DIGEST: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.4");
+const ID_SIGNING_TIME: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.5");
+const ID_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+const RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const SHA256_WITH_RSA_ENCRYPTION: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.11");
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("DER encoding / decoding failed: {0}")]
+    Der(#[from] der::Error),
+
+    #[error("The message is not a CMS SignedData")]
+    NotSignedData,
+
+    #[error("No signer information is found in the signature")]
+    SignerNotFound,
+
+    #[error("The certificate of the signer is not embedded in the signature")]
+    CertificateNotFound,
+
+    #[error("Unsupported algorithm: {0}")]
+    UnsupportedAlgorithm(ObjectIdentifier),
+
+    #[error("The message digest does not match the content")]
+    DigestMismatch,
+
+    #[error("The signature is not valid")]
+    InvalidSignature,
+
+    #[error("The certificate is expired or not yet valid")]
+    CertificateExpired,
+
+    #[error("The certificate chain is not anchored to any trusted CA")]
+    NotAnchored,
+}
+
+/// Parses a DER-encoded certificate read from the card.
+pub fn parse_certificate(der: &[u8]) -> Result<Certificate, Error> {
+    Ok(Certificate::from_der(der)?)
+}
+
+/// Computes the SHA-1 fingerprint of the certificate, in uppercase hex.
+pub fn fingerprint(certificate: &Certificate) -> Result<String, Error> {
+    let der = certificate.to_der()?;
+    let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, &der);
+
+    Ok(hex::encode_upper(digest.as_ref()))
+}
+
+/// Makes a detached CMS SignedData of the content, as DER-encoded ContentInfo.
+/// The first certificate in the chain must be the one of the signer,
+/// and `sign` is called with a DigestInfo to be signed by the corresponding key.
+pub fn sign<F, E>(chain: &[Certificate], content: &[u8], sign: F) -> Result<Vec<u8>, E>
+where
+    F: FnOnce(Vec<u8>) -> Result<Vec<u8>, E>,
+    E: From<Error>,
+{
+    let signer = chain.first().ok_or(Error::CertificateNotFound)?;
+    let signing_time = UtcTime::from_date_time(
+        DateTime::from_unix_duration(SystemTime::now().duration_since(UNIX_EPOCH).unwrap())
+            .map_err(Error::from)?,
+    )
+    .map_err(Error::from)?;
+
+    let signed_attrs = SetOfVec::try_from(vec![
+        attribute(ID_CONTENT_TYPE, Any::encode_from(&ID_DATA))?,
+        attribute(ID_SIGNING_TIME, Any::encode_from(&signing_time))?,
+        attribute(
+            ID_MESSAGE_DIGEST,
+            Any::encode_from(&OctetString::new(digest::sha256(content)).map_err(Error::from)?),
+        )?,
+    ])
+    .map_err(Error::from)?;
+
+    let to_be_signed = signed_attrs.to_der().map_err(Error::from)?;
+    let signature = sign(digest::wrap_sha256(&digest::sha256(&to_be_signed)))?;
+
+    let signer_info = SignerInfo {
+        version: CmsVersion::V1,
+        sid: SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+            issuer: signer.tbs_certificate.issuer.clone(),
+            serial_number: signer.tbs_certificate.serial_number.clone(),
+        }),
+        digest_alg: sha256(),
+        signed_attrs: Some(signed_attrs),
+        signature_algorithm: AlgorithmIdentifierOwned {
+            oid: RSA_ENCRYPTION,
+            parameters: Some(Any::null()),
+        },
+        signature: OctetString::new(signature).map_err(Error::from)?,
+        unsigned_attrs: None,
+    };
+
+    let certificates = chain
+        .iter()
+        .cloned()
+        .map(CertificateChoices::Certificate)
+        .collect::<Vec<_>>();
+
+    let signed_data = SignedData {
+        version: CmsVersion::V1,
+        digest_algorithms: SetOfVec::try_from(vec![sha256()]).map_err(Error::from)?,
+        encap_content_info: EncapsulatedContentInfo {
+            econtent_type: ID_DATA,
+            econtent: None,
+        },
+        certificates: Some(CertificateSet(
+            SetOfVec::try_from(certificates).map_err(Error::from)?,
+        )),
+        crls: None,
+        signer_infos: SignerInfos(SetOfVec::try_from(vec![signer_info]).map_err(Error::from)?),
+    };
+
+    let content_info = ContentInfo {
+        content_type: ID_SIGNED_DATA,
+        content: Any::encode_from(&signed_data).map_err(Error::from)?,
+    };
+
+    Ok(content_info.to_der().map_err(Error::from)?)
+}
+
+/// Verifies the detached CMS SignedData against the content.
+/// Returns the certificate of the signer if the signature is valid.
+pub fn verify(signature: &[u8], content: &[u8]) -> Result<Certificate, Error> {
+    let content_info = ContentInfo::from_der(signature)?;
+    if content_info.content_type != ID_SIGNED_DATA {
+        return Err(Error::NotSignedData);
+    }
+
+    let signed_data = content_info.content.decode_as::<SignedData>()?;
+    let signer_info = signed_data
+        .signer_infos
+        .0
+        .iter()
+        .next()
+        .ok_or(Error::SignerNotFound)?;
+
+    if signer_info.digest_alg.oid != ID_SHA256 {
+        return Err(Error::UnsupportedAlgorithm(signer_info.digest_alg.oid));
+    }
+
+    if signer_info.signature_algorithm.oid != RSA_ENCRYPTION
+        && signer_info.signature_algorithm.oid != SHA256_WITH_RSA_ENCRYPTION
+    {
+        return Err(Error::UnsupportedAlgorithm(
+            signer_info.signature_algorithm.oid,
+        ));
+    }
+
+    let certificate = find_signer(&signed_data, &signer_info.sid)?;
+    let message = match &signer_info.signed_attrs {
+        Some(attrs) => {
+            let message_digest = attrs
+                .iter()
+                .find(|attr| attr.oid == ID_MESSAGE_DIGEST)
+                .and_then(|attr| attr.values.iter().next())
+                .ok_or(Error::DigestMismatch)?
+                .decode_as::<OctetString>()?;
+
+            if message_digest.as_bytes() != digest::sha256(content).as_slice() {
+                return Err(Error::DigestMismatch);
+            }
+
+            attrs.to_der()?
+        }
+        None => content.to_vec(),
+    };
+
+    verify_signature(&certificate, &message, signer_info.signature.as_bytes())?;
+
+    Ok(certificate)
+}
+
+/// Extracts the certificates embedded in the CMS SignedData.
+pub fn certificates(signature: &[u8]) -> Result<Vec<Certificate>, Error> {
+    let content_info = ContentInfo::from_der(signature)?;
+    if content_info.content_type != ID_SIGNED_DATA {
+        return Err(Error::NotSignedData);
+    }
+
+    let signed_data = content_info.content.decode_as::<SignedData>()?;
+
+    Ok(signed_data
+        .certificates
+        .iter()
+        .flat_map(|set| set.0.iter())
+        .filter_map(|choice| match choice {
+            CertificateChoices::Certificate(c) => Some(c.clone()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Verifies the signer is anchored to any of the trusted CAs through the certificates embedded in
+/// the signature, and is valid at the current time.
+pub fn verify_chain(
+    signature: &[u8],
+    signer: &Certificate,
+    anchors: &[Certificate],
+) -> Result<(), Error> {
+    verify_anchored(signer, &certificates(signature)?, anchors)?;
+    verify_validity(signer)
+}
+
+/// Verifies the certificate is issued by any of the trusted CAs, directly or through the
+/// intermediates.
+/// Anyone can embed their own CA in the signature, so the chain must end at a trusted one.
+pub fn verify_anchored(
+    certificate: &Certificate,
+    intermediates: &[Certificate],
+    anchors: &[Certificate],
+) -> Result<(), Error> {
+    let mut current = certificate;
+
+    // Each intermediate appears at most once in the chain, not to loop forever.
+    for _ in 0..=intermediates.len() {
+        let anchored = anchors
+            .iter()
+            .any(|anchor| anchor == current || verify_issued_by(current, anchor).is_ok());
+        if anchored {
+            return Ok(());
+        }
+
+        let issuer = intermediates
+            .iter()
+            .find(|c| *c != current && c.tbs_certificate.subject == current.tbs_certificate.issuer)
+            .ok_or(Error::NotAnchored)?;
+
+        verify_issued_by(current, issuer)?;
+        current = issuer;
+    }
+
+    Err(Error::NotAnchored)
+}
+
+/// Checks the certificate is valid at the current time.
+pub fn verify_validity(certificate: &Certificate) -> Result<(), Error> {
+    let now = SystemTime::now();
+    let validity = &certificate.tbs_certificate.validity;
+
+    match validity.not_before.to_system_time() <= now && now <= validity.not_after.to_system_time()
+    {
+        true => Ok(()),
+        _ => Err(Error::CertificateExpired),
+    }
+}
+
+/// Verifies the RSA PKCS#1 v1.5 signature with SHA-256, using the public key in the certificate.
+pub fn verify_signature(
+    certificate: &Certificate,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), Error> {
+    ring::signature::UnparsedPublicKey::new(
+        &ring::signature::RSA_PKCS1_2048_8192_SHA256,
+        certificate
+            .tbs_certificate
+            .subject_public_key_info
+            .subject_public_key
+            .raw_bytes(),
+    )
+    .verify(message, signature)
+    .map_err(|_| Error::InvalidSignature)
+}
+
+/// Verifies the certificate is issued by the issuer, by checking the signature on them.
+pub fn verify_issued_by(certificate: &Certificate, issuer: &Certificate) -> Result<(), Error> {
+    if certificate.signature_algorithm.oid != SHA256_WITH_RSA_ENCRYPTION {
+        return Err(Error::UnsupportedAlgorithm(
+            certificate.signature_algorithm.oid,
+        ));
+    }
+
+    if certificate.tbs_certificate.issuer != issuer.tbs_certificate.subject {
+        return Err(Error::InvalidSignature);
+    }
+
+    verify_signature(
+        issuer,
+        &certificate.tbs_certificate.to_der()?,
+        certificate.signature.raw_bytes(),
+    )
+}
+
+fn find_signer(signed_data: &SignedData, sid: &SignerIdentifier) -> Result<Certificate, Error> {
+    let SignerIdentifier::IssuerAndSerialNumber(sid) = sid else {
+        return Err(Error::CertificateNotFound);
+    };
+
+    signed_data
+        .certificates
+        .iter()
+        .flat_map(|set| set.0.iter())
+        .find_map(|choice| match choice {
+            CertificateChoices::Certificate(c)
+                if c.tbs_certificate.issuer == sid.issuer
+                    && c.tbs_certificate.serial_number == sid.serial_number =>
+            {
+                Some(c.clone())
+            }
+            _ => None,
+        })
+        .ok_or(Error::CertificateNotFound)
+}
+
+fn attribute(oid: ObjectIdentifier, value: der::Result<Any>) -> Result<Attribute, Error> {
+    Ok(Attribute {
+        oid,
+        values: SetOfVec::try_from(vec![value?])?,
+    })
+}
+
+fn sha256() -> AlgorithmIdentifierOwned {
+    AlgorithmIdentifierOwned {
+        oid: ID_SHA256,
+        parameters: None,
+    }
+}
@@ -13,14 +13,13 @@ struct Signature<'a> {
     bytes: OctetStringRef<'a>,
 }
 
-pub fn calculate(message: Vec<u8>) -> Vec<u8> {
-    let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, &message);
+fn encode(oid: &str, digest: &[u8]) -> Vec<u8> {
     let signature = Signature {
         meta: SignatureMeta {
-            oid: "1.3.14.3.2.26".parse::<ObjectIdentifier>().unwrap(),
+            oid: oid.parse::<ObjectIdentifier>().unwrap(),
             null: Null,
         },
-        bytes: OctetStringRef::new(digest.as_ref()).unwrap(),
+        bytes: OctetStringRef::new(digest).unwrap(),
     };
 
     let mut vec = Vec::new();
@@ -28,6 +27,23 @@ pub fn calculate(message: Vec<u8>) -> Vec<u8> {
     vec
 }
 
+pub fn calculate(message: Vec<u8>) -> Vec<u8> {
+    let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, &message);
+
+    encode("1.3.14.3.2.26", digest.as_ref())
+}
+
+/// Wraps the SHA-256 digest into a DigestInfo structure to be signed by the card.
+pub fn wrap_sha256(digest: &[u8]) -> Vec<u8> {
+    encode("2.16.840.1.101.3.4.2.1", digest)
+}
+
+pub fn sha256(message: &[u8]) -> Vec<u8> {
+    ring::digest::digest(&ring::digest::SHA256, message)
+        .as_ref()
+        .to_vec()
+}
+
 pub fn verify(certificate: Vec<u8>, message: Vec<u8>, signature: Vec<u8>) -> bool {
     let x509 = x509_certificate::X509Certificate::from_der(certificate).unwrap();
     let public_key = x509.public_key_data();
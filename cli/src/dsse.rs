@@ -0,0 +1,143 @@
+//! DSSE (Dead Simple Signing Envelope) support to sign in-toto statements using the card.
+//! Refer <https://github.com/secure-systems-lab/dsse> for details of the format.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use der::pem::LineEnding;
+use der::EncodePem;
+use x509_cert::Certificate;
+
+use crate::{cms, digest};
+
+/// Payload type of in-toto statements.
+pub const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Base64 decoding failed: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("Certificate error: {0}")]
+    Certificate(#[from] cms::Error),
+
+    #[error("The envelope has no signatures")]
+    NoSignatures,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Signature {
+    /// Hint of the key, that holds the PEM-encoded certificate chain of the signer.
+    pub keyid: String,
+
+    /// Base64-encoded signature.
+    pub sig: String,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Envelope {
+    /// Base64-encoded payload.
+    pub payload: String,
+
+    #[serde(rename = "payloadType")]
+    pub payload_type: String,
+
+    pub signatures: Vec<Signature>,
+}
+
+/// Encodes the payload with its type into PAE (Pre-Authentication Encoding), which is to be signed.
+pub fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut buf = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len(),
+    )
+    .into_bytes();
+
+    buf.extend_from_slice(payload);
+    buf
+}
+
+/// Makes an envelope of the payload, signed by the key that corresponds with the first certificate
+/// in the chain. `sign` is called with a DigestInfo to be signed by the key.
+pub fn sign<F, E>(
+    payload_type: &str,
+    payload: &[u8],
+    chain: &[Certificate],
+    sign: F,
+) -> Result<Envelope, E>
+where
+    F: FnOnce(Vec<u8>) -> Result<Vec<u8>, E>,
+    E: From<Error>,
+{
+    let keyid = chain
+        .iter()
+        .map(|c| c.to_pem(LineEnding::LF))
+        .collect::<der::Result<String>>()
+        .map_err(cms::Error::from)
+        .map_err(Error::from)?;
+
+    let signature = sign(digest::wrap_sha256(&digest::sha256(&pae(
+        payload_type,
+        payload,
+    ))))?;
+
+    Ok(Envelope {
+        payload: BASE64.encode(payload),
+        payload_type: payload_type.to_string(),
+        signatures: vec![Signature {
+            keyid,
+            sig: BASE64.encode(signature),
+        }],
+    })
+}
+
+/// Verifies the envelope using the certificate chain in the key hint, that must be anchored to any
+/// of the trusted CAs.
+/// Returns the payload and the certificate of the signer, if any of the signatures is valid.
+pub fn verify(
+    envelope: &Envelope,
+    anchors: &[Certificate],
+) -> Result<(Vec<u8>, Certificate), Error> {
+    let payload = BASE64.decode(&envelope.payload)?;
+    let message = pae(&envelope.payload_type, &payload);
+    let mut last_error = Error::NoSignatures;
+
+    for signature in &envelope.signatures {
+        match verify_signature(signature, &message, anchors) {
+            Ok(certificate) => return Ok((payload, certificate)),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+fn verify_signature(
+    signature: &Signature,
+    message: &[u8],
+    anchors: &[Certificate],
+) -> Result<Certificate, Error> {
+    let chain =
+        Certificate::load_pem_chain(signature.keyid.as_bytes()).map_err(cms::Error::from)?;
+    let (signer, intermediates) = chain.split_first().ok_or(cms::Error::CertificateNotFound)?;
+
+    cms::verify_anchored(signer, intermediates, anchors)?;
+    cms::verify_validity(signer)?;
+    cms::verify_signature(signer, message, &BASE64.decode(&signature.sig)?)?;
+
+    Ok(signer.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pae() {
+        assert_eq!(
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world".to_vec(),
+            pae("http://example.com/HelloWorld", b"hello world"),
+        );
+    }
+}
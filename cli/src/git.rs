@@ -0,0 +1,97 @@
+//! Signing program for Git, compatible with the interface of gpgsm (gpg.format=x509).
+
+use std::io::{stderr, stdout, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use der::pem::LineEnding;
+use x509_cert::Certificate;
+
+use crate::cms;
+
+const PEM_LABEL: &str = "SIGNED MESSAGE";
+
+/// A writer for status lines, that Git reads from the file descriptor specified in `--status-fd`.
+pub struct Status {
+    fd: Option<i32>,
+}
+
+impl Status {
+    pub fn new(fd: Option<i32>) -> Self {
+        Self { fd }
+    }
+
+    /// Emits a line with the `[GNUPG:]` prefix.
+    pub fn emit(&self, line: &str) -> std::io::Result<()> {
+        let line = format!("[GNUPG:] {line}\n");
+
+        match self.fd {
+            Some(1) => stdout().write_all(line.as_bytes()),
+            Some(2) => stderr().write_all(line.as_bytes()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Armors the DER-encoded signature into PEM, as gpgsm does.
+pub fn armor(signature: &[u8]) -> Result<String, cms::Error> {
+    Ok(der::pem::encode_string(PEM_LABEL, LineEnding::LF, signature).map_err(der::Error::from)?)
+}
+
+/// Reads the signature, either armored or not.
+pub fn dearmor(signature: &[u8]) -> Result<Vec<u8>, cms::Error> {
+    match signature.starts_with(b"-----BEGIN ") {
+        true => Ok(der::pem::decode_vec(signature).map_err(der::Error::from)?.1),
+        _ => Ok(signature.to_vec()),
+    }
+}
+
+/// Emits the statuses after creating a detached signature.
+pub fn emit_signed(status: &Status, signer: &Certificate) -> Result<(), crate::Error> {
+    status.emit("BEGIN_SIGNING")?;
+    status.emit(&format!(
+        "SIG_CREATED D 1 8 00 {} {}",
+        unix_time(),
+        cms::fingerprint(signer)?,
+    ))?;
+
+    Ok(())
+}
+
+/// Emits the statuses after verifying a detached signature and the chain of the signer, that is
+/// fully trusted only if anchored to any of the trusted CAs.
+pub fn emit_verified(
+    status: &Status,
+    result: &Result<Certificate, cms::Error>,
+) -> Result<(), crate::Error> {
+    status.emit("NEWSIG")?;
+
+    match result {
+        Ok(signer) => {
+            let subject = signer.tbs_certificate.subject.to_string();
+
+            status.emit(&format!(
+                "GOODSIG {} {subject}",
+                hex::encode_upper(signer.tbs_certificate.serial_number.as_bytes()),
+            ))?;
+            status.emit(&format!("VALIDSIG {}", cms::fingerprint(signer)?))?;
+            status.emit("TRUST_FULLY 0 shell")?;
+        }
+        Err(cms::Error::DigestMismatch | cms::Error::InvalidSignature) => {
+            status.emit("BADSIG 0 unknown")?;
+        }
+        Err(_) => {
+            // The signature cannot be checked, or the signer is not anchored to any trusted CA or
+            // expired.
+            status.emit(&format!("ERRSIG 0 1 8 00 {} 9", unix_time()))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
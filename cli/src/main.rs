@@ -1,17 +1,33 @@
+mod asic;
+mod cms;
 mod digest;
+mod dsse;
+mod git;
+mod openpgp;
+mod smime;
 
 use std::env;
 use std::fs::File;
-use std::io::{stderr, stdin, stdout, Read, Write};
+use std::io::{stderr, stdin, stdout, BufReader, ErrorKind, Read, Write};
 use std::path::PathBuf;
 use std::process::exit;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
 
 use clap::{Parser, Subcommand};
-use dialoguer::Password;
-use jpki::ap::crypto::CertType;
+use console::Term;
+use dialoguer::{Confirm, Password};
+use jpki::ap::crypto::{CertType, Key};
 use jpki::ap::surface::Pin;
-use jpki::pcsc::Context;
+use jpki::ap::CryptoAp;
+use jpki::audit::AuditLog;
+use jpki::card::RetryGuard;
+use jpki::confirm::ConfirmPolicy;
+use jpki::pcsc::{Context, PcscCard};
+use jpki::secret::SecretPin;
+use jpki::session::Session;
+use once_cell::unsync::OnceCell;
 use rust_i18n::{i18n, set_locale, t};
 use tracing::metadata::LevelFilter;
 use tracing::{error, info};
@@ -32,6 +48,30 @@ enum Error {
 
     #[error("JSON serializing / deserializing failed: {0}")]
     Json(#[from] serde_json::Error),
+
+    #[error("CMS signature error: {0}")]
+    Cms(#[from] cms::Error),
+
+    #[error("OpenPGP error: {0}")]
+    Openpgp(#[from] openpgp::Error),
+
+    #[error("DSSE envelope error: {0}")]
+    Dsse(#[from] dsse::Error),
+
+    #[error("S/MIME error: {0}")]
+    Smime(#[from] smime::Error),
+
+    #[error("ASiC container error: {0}")]
+    Asic(#[from] asic::Error),
+
+    #[error("Audit log error: {0}")]
+    Audit(#[from] jpki::audit::Error),
+
+    #[error("The card is not a My Number card")]
+    NotMyNumberCard,
+
+    #[error("Only one retry of the PIN is left, add --force to enter them anyway")]
+    LastRetry,
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -60,6 +100,18 @@ enum SurfacePinType {
     B,
 }
 
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ConfirmMode {
+    /// Never confirms.
+    Never,
+
+    /// Confirms only on signing using the key-pair for signing.
+    Sign,
+
+    /// Always confirms.
+    Always,
+}
+
 #[derive(Clone, clap::ValueEnum)]
 enum SupportContentType {
     MyNumber,
@@ -88,6 +140,67 @@ enum CryptoApAction {
 
     /// Gets the status of PIN.
     Stat,
+
+    /// Exports the public key in OpenPGP format, self-certified using the card.
+    PgpExport {
+        /// User ID to bind with the key, such as "Your Name <you@example.com>".
+        user_id: String,
+    },
+
+    /// Writes a detached OpenPGP signature of the document.
+    PgpSign {
+        /// Path to write the armored signature.
+        signature_path: PathBuf,
+    },
+
+    /// Signs the in-toto statement, then writes a DSSE envelope of them.
+    Attest {
+        /// Path to the statement in JSON.
+        statement_path: PathBuf,
+    },
+
+    /// Verifies the DSSE envelope, then writes the statement in them.
+    VerifyAttestation {
+        /// Path to the envelope in JSON.
+        envelope_path: PathBuf,
+
+        /// Path to the DER-encoded certificate of the CA to trust, such as one read by
+        /// `crypto --ca read-certificate`. Can be specified multiple times.
+        #[clap(long = "anchor")]
+        anchors: Vec<PathBuf>,
+    },
+
+    /// Signs the email from stdin, then writes a multipart/signed S/MIME message.
+    SmimeSign,
+
+    /// Verifies the multipart/signed S/MIME message from stdin, then writes the signed content.
+    SmimeVerify {
+        /// Path to the DER-encoded certificate of the CA to trust, such as one read by
+        /// `crypto --ca read-certificate`. Can be specified multiple times.
+        #[clap(long = "anchor")]
+        anchors: Vec<PathBuf>,
+    },
+
+    /// Creates an ASiC-E container of the files, signed using the signing key.
+    AsicCreate {
+        /// Path to write the container.
+        container_path: PathBuf,
+
+        /// Paths to the files to put into the container.
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Verifies the ASiC-E container, then prints the signed files in them.
+    AsicVerify {
+        /// Path to the container.
+        container_path: PathBuf,
+
+        /// Path to the DER-encoded certificate of the CA to trust, such as one read by
+        /// `crypto --ca read-certificate`. Can be specified multiple times.
+        #[clap(long = "anchor")]
+        anchors: Vec<PathBuf>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -123,6 +236,16 @@ enum SupportApAction {
     Stat,
 }
 
+#[derive(Subcommand)]
+enum AuditAction {
+    /// Verifies the hash chain of the audit log, then prints the number of entries and the hash of
+    /// the last one.
+    Verify {
+        /// Path to the audit log.
+        path: PathBuf,
+    },
+}
+
 #[derive(Subcommand)]
 enum SubCommand {
     /// Read certificates, sign or verify documents.
@@ -137,6 +260,11 @@ enum SubCommand {
         /// While reading certificates, reads their CA certificate instead.
         #[clap(short, long, action)]
         ca: bool,
+
+        /// Enters the PIN on the PIN pad of the reader, instead of the keyboard.
+        /// Available on reading certificates and signing.
+        #[clap(long, action)]
+        pin_pad: bool,
     },
 
     /// Reads the surface information from the card.
@@ -150,6 +278,53 @@ enum SubCommand {
         #[clap(subcommand)]
         action: SupportApAction,
     },
+
+    /// Lists the readers connected to the PC/SC service.
+    Readers,
+
+    /// Identifies the card by the ATR and the APs, to check whether it is a My Number card.
+    Identify,
+
+    /// Verifies the audit log of signatures.
+    Audit {
+        #[clap(subcommand)]
+        action: AuditAction,
+    },
+
+    /// Signs or verifies Git objects, compatible with gpgsm (gpg.format=x509).
+    GitSign {
+        /// File descriptor to write the status lines.
+        #[clap(long)]
+        status_fd: Option<i32>,
+
+        /// Makes a detached signature.
+        #[clap(short = 'b', long, action)]
+        detach_sign: bool,
+
+        /// Makes a signature.
+        #[clap(short, long, action)]
+        sign: bool,
+
+        /// Creates ASCII armored output.
+        #[clap(short, long, action)]
+        armor: bool,
+
+        /// Key to sign with. Ignored, as the key-pair for signing in the card is always used.
+        #[clap(short = 'u', long)]
+        local_user: Option<String>,
+
+        /// Verifies the signature.
+        #[clap(long, action)]
+        verify: bool,
+
+        /// Path to the DER-encoded certificate of the CA to trust on verification, such as one read
+        /// by `crypto --ca read-certificate`. Can be specified multiple times.
+        #[clap(long = "anchor")]
+        anchors: Vec<PathBuf>,
+
+        /// Path to the signature and the signed data, on verification.
+        files: Vec<PathBuf>,
+    },
 }
 
 #[derive(Parser)]
@@ -158,16 +333,220 @@ enum SubCommand {
 struct Cli {
     #[clap(subcommand)]
     command: SubCommand,
+
+    /// Uses the reader whose name contains the text, instead of the first one.
+    #[clap(short, long, global = true)]
+    reader: Option<String>,
+
+    /// Gives up waiting for a card after the seconds, instead of waiting forever.
+    #[clap(short, long, global = true)]
+    timeout: Option<u64>,
+
+    /// Captures the APDUs into the file in pcapng format, to be analysed on Wireshark.
+    #[clap(long, global = true)]
+    pcap: Option<PathBuf>,
+
+    /// Traces the APDUs without redacting the PINs and the personal data. Never share the traces.
+    #[clap(long, global = true)]
+    unsafe_full_trace: bool,
+
+    /// Enters the PIN even if only one retry is left, that blocks the PIN on failure.
+    #[clap(long, global = true)]
+    force: bool,
+
+    /// Records every signature in the append-only audit log in the file.
+    #[clap(long, global = true)]
+    audit_log: Option<PathBuf>,
+
+    /// Shows what is being signed and confirms them, before asking the PIN.
+    #[clap(long, global = true, value_enum, default_value = "sign")]
+    confirm: ConfirmMode,
 }
 
-fn pin_prompt(hint: &str) -> Result<Vec<u8>> {
+/// Prompts the PIN, showing the remaining retries.
+/// Refuses to prompt if only one retry is left, unless forced.
+fn pin_prompt(hint: &str, remaining: u8, force: bool) -> Result<SecretPin> {
+    if remaining == 1 && !force {
+        return Err(Error::LastRetry);
+    }
+
+    // Reading from somewhere other than the terminal returns empty, and prompts forever.
+    let term = terminal();
+    if !term.is_term() {
+        return Err(Error::IO(ErrorKind::NotConnected.into()));
+    }
+
     Password::new()
-        .with_prompt(hint)
-        .interact()
-        .map(|p| p.into_bytes())
+        .with_prompt(format!(
+            "{} [{}]",
+            hint,
+            t!("messages.retries_left", count = &remaining.to_string())
+        ))
+        .interact_on(&term)
+        .map(SecretPin::from)
         .map_err(Error::IO)
 }
 
+/// Opens the terminal to interact with the user, even if the standard streams are redirected.
+/// Git reads the status from the standard error while signing, for example.
+fn terminal() -> Term {
+    #[cfg(unix)]
+    if let Ok(tty) = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+    {
+        if let Ok(read) = tty.try_clone() {
+            return Term::read_write_pair(read, tty);
+        }
+    }
+
+    Term::stderr()
+}
+
+/// Builds the policy to confirm signing the document of the size if any, asking on the terminal.
+fn confirm_policy(mode: ConfirmMode, size: Option<usize>) -> ConfirmPolicy {
+    let keys: &[Key] = match mode {
+        ConfirmMode::Never => &[],
+        ConfirmMode::Sign => &[Key::Sign],
+        ConfirmMode::Always => &[Key::Auth, Key::Sign],
+    };
+
+    ConfirmPolicy::new(keys).with_confirmation(move |signing| {
+        let algorithm = signing
+            .algorithm
+            .map(|a| a.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let term = terminal();
+
+        if let Some(size) = size {
+            let _ = term.write_line(&t!("messages.confirm.document", size = &size.to_string()));
+        }
+        let _ = term.write_line(&match signing.key {
+            Key::Auth => t!("messages.confirm.key_auth"),
+            Key::Sign => t!("messages.confirm.key_sign"),
+        });
+        let _ = term.write_line(&t!("messages.confirm.algorithm", algorithm = &algorithm));
+        let _ = term.write_line(&t!(
+            "messages.confirm.digest",
+            fingerprint = &signing.fingerprint()
+        ));
+
+        // Refuses if the terminal is unavailable, not to sign without the confirmation.
+        Confirm::new()
+            .with_prompt(t!("messages.confirm.prompt"))
+            .default(false)
+            .interact_on(&term)
+            .unwrap_or_default()
+    })
+}
+
+fn connect(reader: Option<&str>, timeout: Option<u64>) -> Result<PcscCard> {
+    let ctx = Context::try_new()?;
+    let device = match reader {
+        Some(reader) => ctx.open_matching(|r| r.name.contains(reader))?,
+        _ => ctx.open()?,
+    };
+    let pcsc_card = match timeout {
+        Some(secs) => device.connect_timeout(&ctx, Duration::from_secs(secs))?,
+        _ => device.connect(&ctx)?,
+    };
+
+    Ok(pcsc_card)
+}
+
+/// Connects to the card, failing early if the ATR tells that it cannot be a My Number card.
+/// PINs are guarded from being blocked, unless forced.
+fn connect_my_number_card(
+    reader: Option<&str>,
+    timeout: Option<u64>,
+    force: bool,
+) -> Result<Rc<jpki::Card<PcscCard, ()>>> {
+    let pcsc_card = connect(reader, timeout)?;
+    if !pcsc_card.atr()?.may_be_my_number_card() {
+        return Err(Error::NotMyNumberCard);
+    }
+
+    let retry_guard = RetryGuard::new(1).with_confirmation(move |_| force);
+
+    Ok(Rc::new(
+        jpki::Card::new(Box::new(pcsc_card))
+            .with_transactions()
+            .with_retry_guard(retry_guard),
+    ))
+}
+
+/// Identifies the caller in the audit log, by the user and the command line.
+fn audit_caller() -> String {
+    let user = env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_default();
+
+    format!("{}: {}", user, env::args().collect::<Vec<_>>().join(" "))
+}
+
+/// Maps errors on selecting the DF to [`Error::NotMyNumberCard`], as other cards reject them.
+fn map_open_error(e: jpki::card::Error) -> Error {
+    match e {
+        jpki::card::Error::Apdu(_) => Error::NotMyNumberCard,
+        e => Error::Card(e),
+    }
+}
+
+/// Prompts the PIN for the key-pair, then reads the certificate that corresponds with them.
+/// The PIN is verified once in the session, to be used for signing too.
+fn read_signer_certificate(
+    crypto_ap: &CryptoAp<PcscCard, ()>,
+    auth: bool,
+    force: bool,
+) -> Result<(Session<PcscCard, ()>, x509_cert::Certificate)> {
+    let (session, certificate) = match auth {
+        true => {
+            let pin = pin_prompt(
+                &t!("messages.pin_hint.user_authn"),
+                crypto_ap.auth_pin_status(())?,
+                force,
+            )?;
+            let session = crypto_ap.login_auth((), pin, None)?;
+            let certificate =
+                crypto_ap.read_certificate((), CertType::Auth, SecretPin::default())?;
+
+            (session, certificate)
+        }
+        _ => {
+            let pin = pin_prompt(
+                &t!("messages.pin_hint.signing"),
+                crypto_ap.sign_pin_status(())?,
+                force,
+            )?;
+            let session = crypto_ap.login_sign((), pin, None)?;
+            let certificate =
+                crypto_ap.read_certificate_with_session((), CertType::Sign, &session)?;
+
+            (session, certificate)
+        }
+    };
+
+    Ok((session, cms::parse_certificate(&certificate)?))
+}
+
+/// Reads the CA certificate that issued the certificate of the key-pair.
+fn read_ca_certificate(
+    crypto_ap: &CryptoAp<PcscCard, ()>,
+    auth: bool,
+) -> Result<x509_cert::Certificate> {
+    let ty = match auth {
+        true => CertType::AuthCA,
+        _ => CertType::SignCA,
+    };
+
+    Ok(cms::parse_certificate(&crypto_ap.read_certificate(
+        (),
+        ty,
+        SecretPin::default(),
+    )?)?)
+}
+
 fn read_all<R: Read>(mut r: R) -> Result<Vec<u8>> {
     let mut buffer: Vec<u8> = vec![];
 
@@ -176,17 +555,47 @@ fn read_all<R: Read>(mut r: R) -> Result<Vec<u8>> {
     Ok(buffer)
 }
 
+/// Reads the DER-encoded certificates of the CAs to trust.
+fn read_anchors(paths: &[PathBuf]) -> Result<Vec<x509_cert::Certificate>> {
+    paths
+        .iter()
+        .map(|path| Ok(cms::parse_certificate(&read_all(File::open(path)?)?)?))
+        .collect()
+}
+
 fn run() -> Result<()> {
     let cli: Cli = Cli::parse();
 
-    let ctx = Context::try_new()?;
-    let device = ctx.open()?;
-    let pcsc_card = device.connect(ctx)?;
+    jpki::redact::set_unsafe_full_trace(cli.unsafe_full_trace);
 
-    let card = Rc::new(jpki::Card::new(Box::new(pcsc_card)));
-    let open_crypto_ap = || jpki::ap::CryptoAp::open((), Rc::clone(&card));
-    let open_surface_ap = || jpki::ap::SurfaceAp::open((), Rc::clone(&card));
-    let open_support_ap = || jpki::ap::SupportAp::open((), Rc::clone(&card));
+    if let Some(path) = &cli.pcap {
+        jpki::pcap::install(File::create(path)?)?;
+    }
+
+    // Connects to the card lazily, as some commands do not require them.
+    let card = OnceCell::new();
+    let card = || {
+        card.get_or_try_init(|| {
+            connect_my_number_card(cli.reader.as_deref(), cli.timeout, cli.force)
+        })
+        .map(Rc::clone)
+    };
+    let open_crypto_ap = || -> Result<_> {
+        let crypto_ap = jpki::ap::CryptoAp::open((), card()?).map_err(map_open_error)?;
+
+        Ok(match &cli.audit_log {
+            Some(path) => crypto_ap.with_audit_log(Arc::new(AuditLog::open(path)?), audit_caller()),
+            _ => crypto_ap,
+        })
+    };
+    // Confirms what is being signed, on the commands to sign.
+    let open_signing_ap = |size: Option<usize>| -> Result<_> {
+        Ok(open_crypto_ap()?.with_confirm_policy(confirm_policy(cli.confirm, size)))
+    };
+    let open_surface_ap =
+        || -> Result<_> { jpki::ap::SurfaceAp::open((), card()?).map_err(map_open_error) };
+    let open_support_ap =
+        || -> Result<_> { jpki::ap::SupportAp::open((), card()?).map_err(map_open_error) };
 
     let to_json = |pretty: bool| match pretty {
         true => serde_json::to_string_pretty,
@@ -194,7 +603,12 @@ fn run() -> Result<()> {
     };
 
     match &cli.command {
-        SubCommand::Crypto { action, auth, ca } => {
+        SubCommand::Crypto {
+            action,
+            auth,
+            ca,
+            pin_pad,
+        } => {
             let ty = match (auth, ca) {
                 (true, true) => CertType::AuthCA,
                 (true, _) => CertType::Auth,
@@ -205,27 +619,60 @@ fn run() -> Result<()> {
             match action {
                 CryptoApAction::ReadCertificate => {
                     let crypto_ap = open_crypto_ap()?;
-                    let pin = if ty.is_pin_required() {
-                        pin_prompt(&t!("messages.pin_hint.signing"))?
-                    } else {
-                        vec![]
+                    let certificate = match (pin_pad, ty.is_pin_required()) {
+                        (true, true) => {
+                            info!("{}", t!("messages.pin_pad"));
+                            crypto_ap.read_certificate_on_pad((), ty)?
+                        }
+                        (_, true) => crypto_ap.read_certificate(
+                            (),
+                            ty,
+                            pin_prompt(
+                                &t!("messages.pin_hint.signing"),
+                                crypto_ap.sign_pin_status(())?,
+                                cli.force,
+                            )?,
+                        )?,
+                        _ => crypto_ap.read_certificate((), ty, SecretPin::default())?,
                     };
 
-                    let certificate = crypto_ap.read_certificate((), ty, pin)?;
                     stdout().write_all(&certificate)?;
                 }
                 CryptoApAction::Sign { signature_path } => {
+                    let document = read_all(stdin())?;
+                    let size = document.len();
+                    let digest = digest::calculate(document);
+                    let key = match auth {
+                        true => Key::Auth,
+                        _ => Key::Sign,
+                    };
+
+                    confirm_policy(cli.confirm, Some(size)).confirm(key, &digest)?;
+
                     let crypto_ap = open_crypto_ap()?;
-                    let digest = digest::calculate(read_all(stdin())?);
-                    let signature = match auth {
-                        true => crypto_ap.auth(
+                    if *pin_pad {
+                        info!("{}", t!("messages.pin_pad"));
+                    }
+
+                    let signature = match (auth, pin_pad) {
+                        (true, true) => crypto_ap.auth_on_pad((), digest),
+                        (true, _) => crypto_ap.auth(
                             (),
-                            pin_prompt(&t!("messages.pin_hint.user_authn"))?,
+                            pin_prompt(
+                                &t!("messages.pin_hint.user_authn"),
+                                crypto_ap.auth_pin_status(())?,
+                                cli.force,
+                            )?,
                             digest,
                         ),
+                        (_, true) => crypto_ap.sign_on_pad((), digest),
                         _ => crypto_ap.sign(
                             (),
-                            pin_prompt(&t!("messages.pin_hint.signing"))?,
+                            pin_prompt(
+                                &t!("messages.pin_hint.signing"),
+                                crypto_ap.sign_pin_status(())?,
+                                cli.force,
+                            )?,
                             digest,
                         ),
                     }?;
@@ -255,6 +702,160 @@ fn run() -> Result<()> {
 
                     println!("{count}");
                 }
+                CryptoApAction::PgpExport { user_id } => {
+                    let crypto_ap = open_signing_ap(None)?;
+                    let (session, certificate) =
+                        read_signer_certificate(&crypto_ap, *auth, cli.force)?;
+                    let key = openpgp::PublicKey::from_certificate(&certificate)?;
+                    let flags = match auth {
+                        true => openpgp::KEY_FLAG_CERTIFY | openpgp::KEY_FLAG_AUTHENTICATE,
+                        _ => openpgp::KEY_FLAG_CERTIFY | openpgp::KEY_FLAG_SIGN,
+                    };
+
+                    let packets = openpgp::certify(&key, user_id, flags, |digest| -> Result<_> {
+                        Ok(match auth {
+                            true => crypto_ap.auth_with_session((), &session, digest),
+                            _ => crypto_ap.sign_with_session((), &session, digest),
+                        }?)
+                    })?;
+
+                    stdout().write_all(openpgp::armor("PUBLIC KEY BLOCK", &packets).as_bytes())?;
+                }
+                CryptoApAction::PgpSign { signature_path } => {
+                    let document = read_all(stdin())?;
+                    let crypto_ap = open_signing_ap(Some(document.len()))?;
+                    let (session, certificate) =
+                        read_signer_certificate(&crypto_ap, *auth, cli.force)?;
+                    let key = openpgp::PublicKey::from_certificate(&certificate)?;
+
+                    let signature = openpgp::sign(&key, &document, |digest| -> Result<_> {
+                        Ok(match auth {
+                            true => crypto_ap.auth_with_session((), &session, digest),
+                            _ => crypto_ap.sign_with_session((), &session, digest),
+                        }?)
+                    })?;
+
+                    let mut signature_file = File::create(signature_path)?;
+                    signature_file.write_all(openpgp::armor("SIGNATURE", &signature).as_bytes())?;
+                }
+                CryptoApAction::Attest { statement_path } => {
+                    let statement = read_all(File::open(statement_path)?)?;
+                    serde_json::from_slice::<serde_json::Value>(&statement)?;
+
+                    let crypto_ap = open_signing_ap(Some(statement.len()))?;
+                    let (session, certificate) =
+                        read_signer_certificate(&crypto_ap, *auth, cli.force)?;
+                    let ca_certificate = read_ca_certificate(&crypto_ap, *auth)?;
+
+                    let envelope = dsse::sign(
+                        dsse::IN_TOTO_PAYLOAD_TYPE,
+                        &statement,
+                        &[certificate, ca_certificate],
+                        |digest| -> Result<_> {
+                            Ok(match auth {
+                                true => crypto_ap.auth_with_session((), &session, digest),
+                                _ => crypto_ap.sign_with_session((), &session, digest),
+                            }?)
+                        },
+                    )?;
+
+                    println!("{}", serde_json::to_string(&envelope)?);
+                }
+                CryptoApAction::SmimeSign => {
+                    let message = read_all(stdin())?;
+                    let crypto_ap = open_signing_ap(Some(message.len()))?;
+                    let (session, certificate) =
+                        read_signer_certificate(&crypto_ap, *auth, cli.force)?;
+                    let ca_certificate = read_ca_certificate(&crypto_ap, *auth)?;
+
+                    let signed = smime::sign(
+                        &message,
+                        &[certificate, ca_certificate],
+                        |digest| -> Result<_> {
+                            Ok(match auth {
+                                true => crypto_ap.auth_with_session((), &session, digest),
+                                _ => crypto_ap.sign_with_session((), &session, digest),
+                            }?)
+                        },
+                    )?;
+
+                    stdout().write_all(&signed)?;
+                }
+                CryptoApAction::SmimeVerify { anchors } => {
+                    match smime::verify(&read_all(stdin())?, &read_anchors(anchors)?) {
+                        Ok((entity, signer)) => {
+                            info!("OK: signed by {}", signer.tbs_certificate.subject);
+                            stdout().write_all(&entity)?;
+                        }
+                        Err(e) => {
+                            error!("NG: {}", e);
+                            exit(1);
+                        }
+                    }
+                }
+                CryptoApAction::AsicCreate {
+                    container_path,
+                    files,
+                } => {
+                    let entries = files
+                        .iter()
+                        .map(|path| {
+                            Ok(asic::Entry {
+                                name: path
+                                    .file_name()
+                                    .map(|n| n.to_string_lossy().to_string())
+                                    .unwrap_or_default(),
+                                content: read_all(File::open(path)?)?,
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+
+                    let size = entries.iter().map(|e| e.content.len()).sum();
+                    let crypto_ap = open_signing_ap(Some(size))?;
+                    let (session, certificate) =
+                        read_signer_certificate(&crypto_ap, false, cli.force)?;
+                    let ca_certificate = read_ca_certificate(&crypto_ap, false)?;
+
+                    asic::create(
+                        File::create(container_path)?,
+                        &entries,
+                        &[certificate, ca_certificate],
+                        |digest| -> Result<_> {
+                            Ok(crypto_ap.sign_with_session((), &session, digest)?)
+                        },
+                    )?;
+                }
+                CryptoApAction::AsicVerify {
+                    container_path,
+                    anchors,
+                } => match asic::verify(File::open(container_path)?, &read_anchors(anchors)?) {
+                    Ok((files, signer)) => {
+                        info!("OK: signed by {}", signer.tbs_certificate.subject);
+                        for file in files {
+                            println!("{}", file);
+                        }
+                    }
+                    Err(e) => {
+                        error!("NG: {}", e);
+                        exit(1);
+                    }
+                },
+                CryptoApAction::VerifyAttestation {
+                    envelope_path,
+                    anchors,
+                } => {
+                    let envelope = serde_json::from_slice(&read_all(File::open(envelope_path)?)?)?;
+                    match dsse::verify(&envelope, &read_anchors(anchors)?) {
+                        Ok((statement, signer)) => {
+                            info!("OK: signed by {}", signer.tbs_certificate.subject);
+                            stdout().write_all(&statement)?;
+                        }
+                        Err(e) => {
+                            error!("NG: {}", e);
+                            exit(1);
+                        }
+                    }
+                }
             }
         }
         SubCommand::Surface { action } => match action {
@@ -262,7 +863,15 @@ fn run() -> Result<()> {
                 use SurfaceContentType::*;
 
                 let surface_ap = open_surface_ap()?;
-                let pin = pin_prompt(t!("messages.pin_hint.surface").as_str())?;
+                // The type is unknown until entered, so the fewer retries are shown.
+                let remaining = surface_ap
+                    .pin_a_status(())?
+                    .min(surface_ap.pin_b_status(())?);
+                let pin = pin_prompt(
+                    t!("messages.pin_hint.surface").as_str(),
+                    remaining,
+                    cli.force,
+                )?;
                 let pin = match pin.len() {
                     12 => Pin::A(pin),
                     _ => Pin::B(pin),
@@ -299,7 +908,11 @@ fn run() -> Result<()> {
                 use SupportContentType::*;
 
                 let support_ap = open_support_ap()?;
-                let pin = pin_prompt(&t!("messages.pin_hint.support"))?;
+                let pin = pin_prompt(
+                    &t!("messages.pin_hint.support"),
+                    support_ap.pin_status(())?,
+                    cli.force,
+                )?;
 
                 match ty {
                     MyNumber => {
@@ -320,6 +933,133 @@ fn run() -> Result<()> {
                 println!("{count}")
             }
         },
+        SubCommand::Readers => {
+            for reader in Context::try_new()?.readers()? {
+                println!(
+                    "{}\t{}{}",
+                    reader.name,
+                    match reader.card_present {
+                        true => "present",
+                        _ => "empty",
+                    },
+                    match reader.is_contactless() {
+                        true => "\tcontactless",
+                        _ => "",
+                    },
+                );
+            }
+        }
+        SubCommand::Identify => {
+            let pcsc_card = connect(cli.reader.as_deref(), cli.timeout)?;
+            let atr = pcsc_card.atr()?;
+
+            println!("ATR\t{}", hex::encode_upper(atr.as_bytes()));
+            println!("Kind\t{:?}", atr.kind());
+
+            if !atr.may_be_my_number_card() {
+                return Err(Error::NotMyNumberCard);
+            }
+
+            let identity = jpki::Card::new(Box::new(pcsc_card))
+                .with_transactions()
+                .identify(())?;
+            let found = |found: bool| match found {
+                true => "found",
+                _ => "not found",
+            };
+
+            println!("Crypto AP\t{}", found(identity.crypto_ap));
+            println!("Surface AP\t{}", found(identity.surface_ap));
+            println!("Support AP\t{}", found(identity.support_ap));
+
+            if !identity.is_my_number_card() {
+                return Err(Error::NotMyNumberCard);
+            }
+        }
+        SubCommand::Audit { action } => match action {
+            AuditAction::Verify { path } => {
+                match jpki::audit::verify(BufReader::new(File::open(path)?)) {
+                    Ok(head) => {
+                        info!("OK");
+                        println!("{}\t{}", head.len, hex::encode(head.hash));
+                    }
+                    Err(e) => {
+                        error!("NG: {}", e);
+                        exit(1);
+                    }
+                }
+            }
+        },
+        SubCommand::GitSign {
+            status_fd,
+            armor,
+            verify,
+            anchors,
+            files,
+            ..
+        } => {
+            let status = git::Status::new(*status_fd);
+
+            match verify {
+                true => {
+                    let [signature_path, ..] = files.as_slice() else {
+                        error!("Path to the signature is required");
+                        exit(2);
+                    };
+
+                    let signature = git::dearmor(&read_all(File::open(signature_path)?)?)?;
+                    let content = match files.get(1) {
+                        Some(path) if path.as_os_str() != "-" => read_all(File::open(path)?)?,
+                        _ => read_all(stdin())?,
+                    };
+
+                    let anchors = read_anchors(anchors)?;
+                    let result = cms::verify(&signature, &content).and_then(|signer| {
+                        cms::verify_chain(&signature, &signer, &anchors)?;
+                        Ok(signer)
+                    });
+                    git::emit_verified(&status, &result)?;
+
+                    match result {
+                        Ok(signer) => {
+                            info!("Good signature from {}", signer.tbs_certificate.subject)
+                        }
+                        Err(e) => {
+                            error!("Bad signature: {}", e);
+                            exit(1);
+                        }
+                    }
+                }
+                _ => {
+                    let content = read_all(stdin())?;
+                    let crypto_ap = open_signing_ap(Some(content.len()))?;
+                    let pin = pin_prompt(
+                        &t!("messages.pin_hint.signing"),
+                        crypto_ap.sign_pin_status(())?,
+                        cli.force,
+                    )?;
+                    let session = crypto_ap.login_sign((), pin, None)?;
+                    let chain = [
+                        crypto_ap.read_certificate_with_session((), CertType::Sign, &session)?,
+                        crypto_ap.read_certificate((), CertType::SignCA, SecretPin::default())?,
+                    ]
+                    .iter()
+                    .map(|c| cms::parse_certificate(c))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+
+                    let signature = cms::sign(&chain, &content, |digest| -> Result<_> {
+                        Ok(crypto_ap.sign_with_session((), &session, digest)?)
+                    })?;
+
+                    match armor {
+                        true => stdout().write_all(git::armor(&signature)?.as_bytes())?,
+                        _ => stdout().write_all(&signature)?,
+                    }
+
+                    git::emit_signed(&status, &chain[0])?;
+                }
+            }
+        }
     }
 
     Ok(())
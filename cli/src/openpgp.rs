@@ -0,0 +1,286 @@
+//! OpenPGP support to export the key-pair in the card and make signatures by them.
+//! Refer RFC 4880 for details of the format.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use der::asn1::UintRef;
+use der::{Decode, Sequence};
+use x509_cert::Certificate;
+
+use crate::digest;
+
+const TAG_SIGNATURE: u8 = 2;
+const TAG_PUBLIC_KEY: u8 = 6;
+const TAG_USER_ID: u8 = 13;
+
+const VERSION: u8 = 4;
+const ALGORITHM_RSA: u8 = 1;
+const ALGORITHM_SHA256: u8 = 8;
+
+const SIGNATURE_BINARY: u8 = 0x00;
+const SIGNATURE_POSITIVE_CERTIFICATION: u8 = 0x13;
+
+const SUBPACKET_CREATION_TIME: u8 = 2;
+const SUBPACKET_ISSUER: u8 = 16;
+const SUBPACKET_KEY_FLAGS: u8 = 27;
+const SUBPACKET_ISSUER_FINGERPRINT: u8 = 33;
+
+/// The key may be used to certify other keys.
+pub const KEY_FLAG_CERTIFY: u8 = 0x01;
+
+/// The key may be used to sign data.
+pub const KEY_FLAG_SIGN: u8 = 0x02;
+
+/// The key may be used for authentication.
+pub const KEY_FLAG_AUTHENTICATE: u8 = 0x20;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("DER encoding / decoding failed: {0}")]
+    Der(#[from] der::Error),
+}
+
+#[derive(Sequence)]
+struct RsaPublicKey<'a> {
+    modulus: UintRef<'a>,
+    public_exponent: UintRef<'a>,
+}
+
+/// An RSA public key in the OpenPGP version 4 format.
+pub struct PublicKey {
+    created_at: u32,
+    modulus: Vec<u8>,
+    public_exponent: Vec<u8>,
+}
+
+impl PublicKey {
+    /// Derives the public key from the certificate.
+    /// The creation time is taken from the beginning of the validity,
+    /// so the same certificate always results in the same fingerprint.
+    pub fn from_certificate(certificate: &Certificate) -> Result<Self, Error> {
+        let tbs = &certificate.tbs_certificate;
+        let key =
+            RsaPublicKey::from_der(tbs.subject_public_key_info.subject_public_key.raw_bytes())?;
+
+        Ok(Self {
+            created_at: tbs.validity.not_before.to_unix_duration().as_secs() as u32,
+            modulus: key.modulus.as_bytes().to_vec(),
+            public_exponent: key.public_exponent.as_bytes().to_vec(),
+        })
+    }
+
+    /// Computes the fingerprint of the key, that is the SHA-1 digest of the key packet.
+    pub fn fingerprint(&self) -> Vec<u8> {
+        let mut buf = vec![0x99];
+        buf.append(&mut prefixed(&self.body(), 2));
+
+        ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, &buf)
+            .as_ref()
+            .to_vec()
+    }
+
+    /// Gets the key ID, that is the low-order 64 bits of the fingerprint.
+    pub fn key_id(&self) -> Vec<u8> {
+        self.fingerprint()[12..].to_vec()
+    }
+
+    fn body(&self) -> Vec<u8> {
+        let mut buf = vec![VERSION];
+        buf.extend_from_slice(&self.created_at.to_be_bytes());
+        buf.push(ALGORITHM_RSA);
+        buf.append(&mut mpi(&self.modulus));
+        buf.append(&mut mpi(&self.public_exponent));
+        buf
+    }
+}
+
+/// Makes a transferable public key, that consists of the public key, the user ID and
+/// the self-certification of them.
+/// `sign` is called with a DigestInfo to be signed by the key.
+pub fn certify<F, E>(key: &PublicKey, user_id: &str, flags: u8, sign: F) -> Result<Vec<u8>, E>
+where
+    F: FnOnce(Vec<u8>) -> Result<Vec<u8>, E>,
+{
+    let key_body = key.body();
+    let user_id = user_id.as_bytes();
+
+    let mut message = vec![0x99];
+    message.append(&mut prefixed(&key_body, 2));
+    message.push(0xB4);
+    message.append(&mut prefixed(user_id, 4));
+
+    let signature = signature(
+        key,
+        SIGNATURE_POSITIVE_CERTIFICATION,
+        vec![subpacket(SUBPACKET_KEY_FLAGS, &[flags])],
+        message,
+        sign,
+    )?;
+
+    let mut buf = packet(TAG_PUBLIC_KEY, &key_body);
+    buf.append(&mut packet(TAG_USER_ID, user_id));
+    buf.append(&mut packet(TAG_SIGNATURE, &signature));
+    Ok(buf)
+}
+
+/// Makes a detached signature of the binary document.
+/// `sign` is called with a DigestInfo to be signed by the key.
+pub fn sign<F, E>(key: &PublicKey, document: &[u8], sign: F) -> Result<Vec<u8>, E>
+where
+    F: FnOnce(Vec<u8>) -> Result<Vec<u8>, E>,
+{
+    let signature = signature(key, SIGNATURE_BINARY, vec![], document.to_vec(), sign)?;
+
+    Ok(packet(TAG_SIGNATURE, &signature))
+}
+
+/// Armors the binary data into ASCII, with the type of block such as `PUBLIC KEY BLOCK`.
+pub fn armor(kind: &str, data: &[u8]) -> String {
+    let encoded = BASE64.encode(data);
+    let mut armored = format!("-----BEGIN PGP {kind}-----\n\n");
+
+    for line in encoded.as_bytes().chunks(64) {
+        armored.push_str(&String::from_utf8_lossy(line));
+        armored.push('\n');
+    }
+
+    armored.push('=');
+    armored.push_str(&BASE64.encode(&crc24(data).to_be_bytes()[1..]));
+    armored.push('\n');
+    armored.push_str(&format!("-----END PGP {kind}-----\n"));
+    armored
+}
+
+fn signature<F, E>(
+    key: &PublicKey,
+    ty: u8,
+    mut subpackets: Vec<Vec<u8>>,
+    mut message: Vec<u8>,
+    sign: F,
+) -> Result<Vec<u8>, E>
+where
+    F: FnOnce(Vec<u8>) -> Result<Vec<u8>, E>,
+{
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or_default();
+
+    let mut issuer_fingerprint = vec![VERSION];
+    issuer_fingerprint.append(&mut key.fingerprint());
+
+    subpackets.insert(
+        0,
+        subpacket(SUBPACKET_CREATION_TIME, &created_at.to_be_bytes()),
+    );
+    subpackets.push(subpacket(SUBPACKET_ISSUER_FINGERPRINT, &issuer_fingerprint));
+
+    let mut hashed = vec![VERSION, ty, ALGORITHM_RSA, ALGORITHM_SHA256];
+    hashed.append(&mut prefixed(&subpackets.concat(), 2));
+
+    message.extend_from_slice(&hashed);
+    message.extend_from_slice(&[VERSION, 0xFF]);
+    message.extend_from_slice(&(hashed.len() as u32).to_be_bytes());
+
+    let hash = digest::sha256(&message);
+    let signature = sign(digest::wrap_sha256(&hash))?;
+
+    let mut buf = hashed;
+    buf.append(&mut prefixed(
+        &subpacket(SUBPACKET_ISSUER, &key.key_id()),
+        2,
+    ));
+    buf.extend_from_slice(&hash[..2]);
+    buf.append(&mut mpi(&signature));
+    Ok(buf)
+}
+
+fn packet(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0xC0 | tag];
+    let len = body.len();
+
+    match len {
+        0..=191 => buf.push(len as u8),
+        192..=8383 => {
+            let len = len - 192;
+            buf.push(((len >> 8) + 192) as u8);
+            buf.push((len & 0xFF) as u8);
+        }
+        _ => {
+            buf.push(0xFF);
+            buf.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+
+    buf.extend_from_slice(body);
+    buf
+}
+
+fn subpacket(ty: u8, body: &[u8]) -> Vec<u8> {
+    // Subpackets in signatures are always shorter than 192 octets.
+    let mut buf = vec![(body.len() + 1) as u8, ty];
+    buf.extend_from_slice(body);
+    buf
+}
+
+fn prefixed(body: &[u8], size: usize) -> Vec<u8> {
+    let mut buf = body.len().to_be_bytes()[(usize::BITS as usize / 8 - size)..].to_vec();
+    buf.extend_from_slice(body);
+    buf
+}
+
+fn mpi(integer: &[u8]) -> Vec<u8> {
+    let integer = match integer.iter().position(|b| *b != 0) {
+        Some(pos) => &integer[pos..],
+        None => &[],
+    };
+
+    let bits = match integer.first() {
+        Some(b) => (integer.len() * 8) as u32 - b.leading_zeros(),
+        None => 0,
+    };
+
+    let mut buf = (bits as u16).to_be_bytes().to_vec();
+    buf.extend_from_slice(integer);
+    buf
+}
+
+fn crc24(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xB704CE;
+
+    for b in data {
+        crc ^= (*b as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= 0x1864CFB;
+            }
+        }
+    }
+
+    crc & 0xFFFFFF
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mpi() {
+        assert_eq!(vec![0x00, 0x01, 0x01], mpi(&[0x00, 0x01]));
+        assert_eq!(vec![0x00, 0x11, 0x01, 0x00, 0x01], mpi(&[0x01, 0x00, 0x01]));
+        assert_eq!(vec![0x00, 0x00], mpi(&[0x00]));
+    }
+
+    #[test]
+    fn test_packet_length() {
+        assert_eq!(vec![0xC2, 0x01, 0xAA], packet(TAG_SIGNATURE, &[0xAA]));
+        assert_eq!(&[0xC2, 0xC0, 0x08], &packet(TAG_SIGNATURE, &[0; 200])[..3]);
+        assert_eq!(
+            &[0xC2, 0xFF, 0x00, 0x00, 0x20, 0xD0],
+            &packet(TAG_SIGNATURE, &[0; 8400])[..6],
+        );
+    }
+}
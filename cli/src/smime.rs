@@ -0,0 +1,273 @@
+//! S/MIME support to sign emails using the card, in the `multipart/signed` format.
+//! Refer RFC 8551 for details of the format.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use x509_cert::Certificate;
+
+use crate::{cms, digest};
+
+const CRLF: &str = "\r\n";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Base64 decoding failed: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("Signature error: {0}")]
+    Cms(#[from] cms::Error),
+
+    #[error("The message is not a multipart/signed message")]
+    NotSigned,
+
+    #[error("The message is malformed: {0}")]
+    Malformed(&'static str),
+
+    #[error("The content contains 8-bit data, that must be encoded in 7 bits to be signed")]
+    EightBit,
+}
+
+/// Signs the RFC 5322 message, then builds a `multipart/signed` message that wraps them.
+/// The first certificate in the chain must be the one of the signer,
+/// and `sign` is called with a DigestInfo to be signed by the corresponding key.
+/// The content must be in 7 bits, such as quoted-printable or base64, as the signature would break
+/// on relays that convert 8-bit data.
+pub fn sign<F, E>(message: &[u8], chain: &[Certificate], sign: F) -> Result<Vec<u8>, E>
+where
+    F: FnOnce(Vec<u8>) -> Result<Vec<u8>, E>,
+    E: From<Error> + From<cms::Error>,
+{
+    let message = canonicalize(message);
+    let (headers, body) = split(&message);
+
+    // Headers describing the content are moved into the signed entity, others are kept outside.
+    let (mut content_headers, outer_headers): (Vec<_>, Vec<_>) = headers
+        .into_iter()
+        .filter(|h| !is_header(h, "MIME-Version"))
+        .partition(|h| is_header(h, "Content-"));
+
+    if !content_headers.iter().any(|h| is_header(h, "Content-Type")) {
+        content_headers.insert(0, "Content-Type: text/plain; charset=us-ascii".to_string());
+    }
+
+    let mut entity = content_headers.join(CRLF).into_bytes();
+    entity.extend_from_slice(CRLF.as_bytes());
+    entity.extend_from_slice(CRLF.as_bytes());
+    entity.extend_from_slice(body);
+
+    if !entity.is_ascii() {
+        return Err(Error::EightBit.into());
+    }
+
+    let signature = cms::sign(chain, &entity, sign)?;
+    let boundary = format!("jpki-{}", &hex::encode(digest::sha256(&signature))[..32]);
+
+    let mut signed = outer_headers;
+    signed.push("MIME-Version: 1.0".to_string());
+    signed.push(format!(
+        "Content-Type: multipart/signed; protocol=\"application/pkcs7-signature\"; micalg=sha-256; boundary=\"{boundary}\""
+    ));
+    signed.push(String::new());
+    signed.push("This is a cryptographically signed message in MIME format.".to_string());
+    signed.push(String::new());
+    signed.push(format!("--{boundary}"));
+
+    let mut buf = signed.join(CRLF).into_bytes();
+    buf.extend_from_slice(CRLF.as_bytes());
+    buf.extend_from_slice(&entity);
+
+    let encoded = BASE64.encode(signature);
+    let mut signature_part = vec![
+        String::new(),
+        format!("--{boundary}"),
+        "Content-Type: application/pkcs7-signature; name=\"smime.p7s\"".to_string(),
+        "Content-Transfer-Encoding: base64".to_string(),
+        "Content-Disposition: attachment; filename=\"smime.p7s\"".to_string(),
+        String::new(),
+    ];
+
+    signature_part.extend(
+        encoded
+            .as_bytes()
+            .chunks(76)
+            .map(|line| String::from_utf8_lossy(line).to_string()),
+    );
+    signature_part.push(String::new());
+    signature_part.push(format!("--{boundary}--"));
+    signature_part.push(String::new());
+
+    buf.extend_from_slice(signature_part.join(CRLF).as_bytes());
+    Ok(buf)
+}
+
+/// Verifies the `multipart/signed` message.
+/// Returns the signed entity and the certificate of the signer if the signature is valid,
+/// and the certificate is anchored to any of the trusted CAs.
+pub fn verify(message: &[u8], anchors: &[Certificate]) -> Result<(Vec<u8>, Certificate), Error> {
+    let message = canonicalize(message);
+    let (headers, body) = split(&message);
+
+    let content_type = headers
+        .iter()
+        .find(|h| is_header(h, "Content-Type"))
+        .ok_or(Error::NotSigned)?;
+
+    if !content_type
+        .to_ascii_lowercase()
+        .contains("multipart/signed")
+    {
+        return Err(Error::NotSigned);
+    }
+
+    let boundary = parameter(content_type, "boundary").ok_or(Error::Malformed("no boundary"))?;
+    let delimiter = format!("{CRLF}--{boundary}");
+
+    // Prepends CRLF to find the first delimiter at the beginning of the body.
+    let body = [CRLF.as_bytes(), body].concat();
+    let parts = split_by(&body, delimiter.as_bytes());
+    let [_preamble, entity, signature, ..] = parts.as_slice() else {
+        return Err(Error::Malformed("missing parts"));
+    };
+
+    let entity = strip_line(entity);
+    let (_, signature) = split(strip_line(signature));
+    let signature = BASE64.decode(
+        signature
+            .iter()
+            .filter(|b| !b.is_ascii_whitespace())
+            .copied()
+            .collect::<Vec<_>>(),
+    )?;
+
+    let signer = cms::verify(&signature, entity)?;
+    cms::verify_chain(&signature, &signer, anchors)?;
+
+    Ok((entity.to_vec(), signer))
+}
+
+/// Converts bare LFs into CRLFs, as required for the canonical form of MIME entities.
+fn canonicalize(message: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(message.len());
+    let mut prev = 0u8;
+
+    for b in message {
+        if *b == b'\n' && prev != b'\r' {
+            buf.push(b'\r');
+        }
+
+        buf.push(*b);
+        prev = *b;
+    }
+
+    buf
+}
+
+/// Splits the canonical entity into unfolded header fields and the body.
+fn split(entity: &[u8]) -> (Vec<String>, &[u8]) {
+    let separator = b"\r\n\r\n";
+    let (head, body) = match entity.windows(4).position(|w| w == separator) {
+        Some(pos) => (&entity[..pos], &entity[pos + separator.len()..]),
+        None => (entity, &[][..]),
+    };
+
+    let mut headers: Vec<String> = vec![];
+    for line in String::from_utf8_lossy(head).split(CRLF) {
+        match (line.starts_with([' ', '\t']), headers.last_mut()) {
+            (true, Some(last)) => {
+                last.push_str(CRLF);
+                last.push_str(line);
+            }
+            _ if !line.is_empty() => headers.push(line.to_string()),
+            _ => (),
+        }
+    }
+
+    (headers, body)
+}
+
+fn split_by<'a>(buf: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut pos = 0;
+
+    while pos + delimiter.len() <= buf.len() {
+        if &buf[pos..pos + delimiter.len()] == delimiter {
+            parts.push(&buf[start..pos]);
+            pos += delimiter.len();
+            start = pos;
+        } else {
+            pos += 1;
+        }
+    }
+
+    parts.push(&buf[start..]);
+    parts
+}
+
+/// Strips the rest of the delimiter line, that may contain transport padding.
+fn strip_line(part: &[u8]) -> &[u8] {
+    match part.windows(2).position(|w| w == CRLF.as_bytes()) {
+        Some(pos) => &part[pos + CRLF.len()..],
+        None => &[],
+    }
+}
+
+fn is_header(header: &str, name: &str) -> bool {
+    header
+        .as_bytes()
+        .get(..name.len())
+        .is_some_and(|h| h.eq_ignore_ascii_case(name.as_bytes()))
+}
+
+fn parameter(header: &str, name: &str) -> Option<String> {
+    header.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+
+        match key.trim().eq_ignore_ascii_case(name) {
+            true => Some(value.trim().trim_matches('"').to_string()),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let message = canonicalize(b"Subject: Hello\n  World\nTo: a@example.com\n\nBody\n");
+        let (headers, body) = split(&message);
+
+        assert_eq!(
+            vec!["Subject: Hello\r\n  World", "To: a@example.com"],
+            headers,
+        );
+        assert_eq!(b"Body\r\n", body);
+    }
+
+    #[test]
+    fn test_is_header() {
+        assert!(is_header("content-type: text/plain", "Content-Type"));
+        assert!(!is_header("あいう", "Subject"));
+    }
+
+    #[test]
+    fn test_sign_eight_bit() {
+        let result = sign(
+            "Subject: Hello\n\nこんにちは\n".as_bytes(),
+            &[],
+            |_| -> Result<_, Error> { unreachable!() },
+        );
+
+        assert!(matches!(result, Err(Error::EightBit)));
+    }
+
+    #[test]
+    fn test_parameter() {
+        let header = "Content-Type: multipart/signed; protocol=\"application/pkcs7-signature\"; boundary=\"abc\"";
+
+        assert_eq!(Some("abc".to_string()), parameter(header, "boundary"));
+        assert_eq!(None, parameter(header, "micalg"));
+    }
+}
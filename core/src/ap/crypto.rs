@@ -1,16 +1,37 @@
 //! Crypto AP (formerly JPKI AP)
 
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::ap::open;
+#[cfg(feature = "async")]
+use crate::ap::open_async;
+#[cfg(feature = "audit")]
+use crate::audit::{AuditLog, Auditor};
+use crate::confirm::ConfirmPolicy;
+use crate::digest_info::DigestPolicy;
+use crate::pinpad::{Charset, PinFormat};
+use crate::secret::SecretPin;
+use crate::session::Session;
 use crate::{card, nfc, Card};
 
-const DF_NAME: [u8; 10] = [0xD3, 0x92, 0xF0, 0x00, 0x26, 0x01, 0x00, 0x00, 0x00, 0x01];
+pub(crate) const DF_NAME: [u8; 10] = [0xD3, 0x92, 0xF0, 0x00, 0x26, 0x01, 0x00, 0x00, 0x00, 0x01];
 const EF_AUTH: [u8; 2] = [0x00, 0x17];
 const EF_AUTH_PIN: [u8; 2] = [0x00, 0x18];
 const EF_SIGN: [u8; 2] = [0x00, 0x1A];
 const EF_SIGN_PIN: [u8; 2] = [0x00, 0x1B];
 
+/// Format of PIN for user authentication, in 4 digits.
+pub const AUTH_PIN_FORMAT: PinFormat = PinFormat::new(4, 4);
+
+/// Format of PIN for signing, in 6 to 16 uppercase letters or digits.
+/// Note that PIN pads can be used only if the PIN consists of digits.
+pub const SIGN_PIN_FORMAT: PinFormat =
+    PinFormat::new(6, 16).with_charset(Charset::UppercaseAlphanumerics);
+
 /// Type of the certificate to fetch
 #[derive(Copy, Clone)]
 pub enum CertType {
@@ -45,68 +66,500 @@ impl CertType {
     }
 }
 
+/// Key-pair to compute signatures with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    /// Key-pair for user authentication
+    Auth,
+
+    /// Key-pair for signing
+    Sign,
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Auth => "auth",
+            Self::Sign => "sign",
+        })
+    }
+}
+
 /// An AP to sign or verify messages using a key-pair issued by JPKI
-pub struct CryptoAp<T, Ctx>
+pub struct CryptoAp<T, Ctx, C = Rc<Card<T, Ctx>>>
 where
     T: nfc::HandlerInCtx<Ctx>,
     Ctx: Copy,
+    C: Deref<Target = Card<T, Ctx>>,
 {
-    card: Rc<Card<T, Ctx>>,
+    card: C,
+    digest_policy: Option<DigestPolicy>,
+    confirm_policy: Option<ConfirmPolicy>,
+    #[cfg(feature = "audit")]
+    auditor: Option<Auditor>,
 }
 
-impl<T, Ctx> CryptoAp<T, Ctx>
+/// A [`CryptoAp`] on the card shared across threads.
+pub type SharedCryptoAp<T, Ctx> = CryptoAp<T, Ctx, Arc<Card<T, Ctx>>>;
+
+open!(
+    CryptoAp,
+    DF_NAME,
+    digest_policy: None,
+    confirm_policy: None,
+    #[cfg(feature = "audit")]
+    auditor: None
+);
+
+impl<T, Ctx, C> CryptoAp<T, Ctx, C>
 where
     T: nfc::HandlerInCtx<Ctx>,
     Ctx: Copy,
+    C: Deref<Target = Card<T, Ctx>> + Clone,
 {
-    open!(T, Ctx, DF_NAME);
+    /// Enables the strict mode, that signs only DigestInfo allowed by the policy.
+    /// Inputs are rejected before the PIN is sent.
+    /// This is opt-in, not to break existing callers that sign raw digests.
+    pub fn with_strict_mode(mut self, policy: DigestPolicy) -> Self {
+        self.digest_policy = Some(policy);
+        self
+    }
+
+    /// Confirms what is being signed with the user by the policy, before the PIN is sent.
+    pub fn with_confirm_policy(mut self, policy: ConfirmPolicy) -> Self {
+        self.confirm_policy = Some(policy);
+        self
+    }
+
+    /// Records every signature in the audit log, with the name of the caller.
+    /// Signatures are discarded if they cannot be recorded.
+    #[cfg(feature = "audit")]
+    pub fn with_audit_log(mut self, log: Arc<AuditLog>, caller: impl Into<String>) -> Self {
+        self.auditor = Some(Auditor::new(log, caller.into()));
+        self
+    }
 
     /// Reads a certificate of the type, unlocking with the PIN if required.
     pub fn read_certificate(
         &self,
         ctx: Ctx,
         ty: CertType,
-        pin: Vec<u8>,
+        pin: SecretPin,
     ) -> Result<Vec<u8>, card::Error> {
-        if ty.is_pin_required() {
-            self.verify_sign_pin(ctx, pin)?;
-        }
+        self.card.transaction_in(ctx, &DF_NAME, || {
+            if ty.is_pin_required() {
+                self.verify_sign_pin(ctx, pin)?;
+            }
 
-        self.card
-            .select_ef(ctx, ty.into_efid().into())
-            .and_then(|_| self.card.read_der_size(ctx))
-            .and_then(|size| self.card.read(ctx, Some(size)))
+            self.card
+                .select_ef(ctx, ty.into_efid().into())
+                .and_then(|_| self.card.read_der_size(ctx))
+                .and_then(|size| self.card.read(ctx, Some(size)))
+        })
     }
 
     /// Computes a signature using the key-pair for authentication.
-    pub fn auth(&self, ctx: Ctx, pin: Vec<u8>, digest: Vec<u8>) -> Result<Vec<u8>, card::Error> {
-        self.verify_auth_pin(ctx, pin)
-            .and_then(|_| self.card.select_ef(ctx, EF_AUTH.into()))
-            .and_then(|_| self.card.sign(ctx, digest))
+    pub fn auth(&self, ctx: Ctx, pin: SecretPin, digest: Vec<u8>) -> Result<Vec<u8>, card::Error> {
+        self.check_digest(&digest)?;
+        self.confirm(Key::Auth, &digest)?;
+
+        self.card.transaction_in(ctx, &DF_NAME, || {
+            self.verify_auth_pin(ctx, pin)
+                .and_then(|_| self.compute_signature(ctx, CertType::Auth, digest))
+        })
     }
 
     /// Computes a signature using the key-pair for signing.
-    pub fn sign(&self, ctx: Ctx, pin: Vec<u8>, digest: Vec<u8>) -> Result<Vec<u8>, card::Error> {
-        self.verify_sign_pin(ctx, pin)
-            .and_then(|_| self.card.select_ef(ctx, EF_SIGN.into()))
-            .and_then(|_| self.card.sign(ctx, digest))
+    pub fn sign(&self, ctx: Ctx, pin: SecretPin, digest: Vec<u8>) -> Result<Vec<u8>, card::Error> {
+        self.check_digest(&digest)?;
+        self.confirm(Key::Sign, &digest)?;
+
+        self.card.transaction_in(ctx, &DF_NAME, || {
+            self.verify_sign_pin(ctx, pin)
+                .and_then(|_| self.compute_signature(ctx, CertType::Sign, digest))
+        })
     }
 
     /// Gets the status of PIN for user authentication.
     pub fn auth_pin_status(&self, ctx: Ctx) -> Result<u8, card::Error> {
-        self.card.pin_status(ctx, EF_AUTH_PIN)
+        self.card
+            .transaction_in(ctx, &DF_NAME, || self.card.pin_status(ctx, EF_AUTH_PIN))
     }
 
     /// Gets the status of PIN for signing.
     pub fn sign_pin_status(&self, ctx: Ctx) -> Result<u8, card::Error> {
-        self.card.pin_status(ctx, EF_SIGN_PIN)
+        self.card
+            .transaction_in(ctx, &DF_NAME, || self.card.pin_status(ctx, EF_SIGN_PIN))
+    }
+
+    /// Verifies the PIN for user authentication once, to compute signatures in the session.
+    pub fn login_auth(
+        &self,
+        ctx: Ctx,
+        pin: SecretPin,
+        timeout: Option<Duration>,
+    ) -> Result<Session<T, Ctx, C>, card::Error> {
+        AUTH_PIN_FORMAT.validate(pin.expose())?;
+        Session::login(ctx, self.card.clone(), &DF_NAME, EF_AUTH_PIN, &pin, timeout)
+    }
+
+    /// Verifies the PIN for signing once, to read the certificate and compute signatures in the
+    /// session.
+    pub fn login_sign(
+        &self,
+        ctx: Ctx,
+        pin: SecretPin,
+        timeout: Option<Duration>,
+    ) -> Result<Session<T, Ctx, C>, card::Error> {
+        SIGN_PIN_FORMAT.validate(pin.expose())?;
+        Session::login(ctx, self.card.clone(), &DF_NAME, EF_SIGN_PIN, &pin, timeout)
+    }
+
+    /// Reads a certificate of the type, in the session for signing.
+    pub fn read_certificate_with_session(
+        &self,
+        ctx: Ctx,
+        ty: CertType,
+        session: &Session<T, Ctx, C>,
+    ) -> Result<Vec<u8>, card::Error> {
+        session.run(ctx, &DF_NAME, EF_SIGN_PIN, || {
+            self.card
+                .select_ef(ctx, ty.into_efid().into())
+                .and_then(|_| self.card.read_der_size(ctx))
+                .and_then(|size| self.card.read(ctx, Some(size)))
+        })
+    }
+
+    /// Computes a signature using the key-pair for authentication, in the session.
+    pub fn auth_with_session(
+        &self,
+        ctx: Ctx,
+        session: &Session<T, Ctx, C>,
+        digest: Vec<u8>,
+    ) -> Result<Vec<u8>, card::Error> {
+        self.check_digest(&digest)?;
+        self.confirm(Key::Auth, &digest)?;
+
+        session.run(ctx, &DF_NAME, EF_AUTH_PIN, || {
+            self.compute_signature(ctx, CertType::Auth, digest)
+        })
+    }
+
+    /// Computes a signature using the key-pair for signing, in the session.
+    pub fn sign_with_session(
+        &self,
+        ctx: Ctx,
+        session: &Session<T, Ctx, C>,
+        digest: Vec<u8>,
+    ) -> Result<Vec<u8>, card::Error> {
+        self.check_digest(&digest)?;
+        self.confirm(Key::Sign, &digest)?;
+
+        session.run(ctx, &DF_NAME, EF_SIGN_PIN, || {
+            self.compute_signature(ctx, CertType::Sign, digest)
+        })
+    }
+
+    fn verify_auth_pin(&self, ctx: Ctx, pin: SecretPin) -> Result<(), card::Error> {
+        AUTH_PIN_FORMAT.validate(pin.expose())?;
+        self.card.verify_pin(ctx, EF_AUTH_PIN, &pin)
+    }
+
+    fn verify_sign_pin(&self, ctx: Ctx, pin: SecretPin) -> Result<(), card::Error> {
+        SIGN_PIN_FORMAT.validate(pin.expose())?;
+        self.card.verify_pin(ctx, EF_SIGN_PIN, &pin)
+    }
+
+    fn check_digest(&self, digest: &[u8]) -> Result<(), card::Error> {
+        match &self.digest_policy {
+            Some(policy) => policy.check(digest).map(|_| ()).map_err(Into::into),
+            None => Ok(()),
+        }
+    }
+
+    fn confirm(&self, key: Key, digest: &[u8]) -> Result<(), card::Error> {
+        match &self.confirm_policy {
+            Some(policy) => policy.confirm(key, digest),
+            None => Ok(()),
+        }
+    }
+
+    /// Computes a signature using the key-pair of the certificate type, with the PIN verified.
+    /// The signature is recorded in the audit log if attached.
+    fn compute_signature(
+        &self,
+        ctx: Ctx,
+        ty: CertType,
+        digest: Vec<u8>,
+    ) -> Result<Vec<u8>, card::Error> {
+        let ef = match ty {
+            CertType::Auth => EF_AUTH,
+            _ => EF_SIGN,
+        };
+
+        #[cfg(feature = "audit")]
+        if let Some(auditor) = &self.auditor {
+            let key = match ty {
+                CertType::Auth => Key::Auth,
+                _ => Key::Sign,
+            };
+
+            // The certificate is read before selecting the key-pair to sign with.
+            let certificate = self
+                .card
+                .select_ef(ctx, ty.into_efid().into())
+                .and_then(|_| self.card.read_der_size(ctx))
+                .and_then(|size| self.card.read(ctx, Some(size)))?;
+            let signature = self
+                .card
+                .select_ef(ctx, ef.into())
+                .and_then(|_| self.card.sign(ctx, digest.clone()))?;
+
+            auditor.record(key, &certificate, &digest)?;
+
+            return Ok(signature);
+        }
+
+        self.card
+            .select_ef(ctx, ef.into())
+            .and_then(|_| self.card.sign(ctx, digest))
+    }
+}
+
+impl<T, Ctx, C> CryptoAp<T, Ctx, C>
+where
+    T: nfc::PinPad<Ctx>,
+    Ctx: Copy,
+    C: Deref<Target = Card<T, Ctx>> + Clone,
+{
+    /// Reads a certificate of the type, unlocking with the PIN entered on the PIN pad if required.
+    pub fn read_certificate_on_pad(&self, ctx: Ctx, ty: CertType) -> Result<Vec<u8>, card::Error> {
+        self.card.transaction_in(ctx, &DF_NAME, || {
+            if ty.is_pin_required() {
+                self.card
+                    .verify_pin_on_pad(ctx, EF_SIGN_PIN, &SIGN_PIN_FORMAT)?;
+            }
+
+            self.card
+                .select_ef(ctx, ty.into_efid().into())
+                .and_then(|_| self.card.read_der_size(ctx))
+                .and_then(|size| self.card.read(ctx, Some(size)))
+        })
+    }
+
+    /// Computes a signature using the key-pair for authentication,
+    /// with the PIN entered on the PIN pad.
+    pub fn auth_on_pad(&self, ctx: Ctx, digest: Vec<u8>) -> Result<Vec<u8>, card::Error> {
+        self.check_digest(&digest)?;
+        self.confirm(Key::Auth, &digest)?;
+
+        self.card.transaction_in(ctx, &DF_NAME, || {
+            self.card
+                .verify_pin_on_pad(ctx, EF_AUTH_PIN, &AUTH_PIN_FORMAT)
+                .and_then(|_| self.compute_signature(ctx, CertType::Auth, digest))
+        })
+    }
+
+    /// Computes a signature using the key-pair for signing, with the PIN entered on the PIN pad.
+    pub fn sign_on_pad(&self, ctx: Ctx, digest: Vec<u8>) -> Result<Vec<u8>, card::Error> {
+        self.check_digest(&digest)?;
+        self.confirm(Key::Sign, &digest)?;
+
+        self.card.transaction_in(ctx, &DF_NAME, || {
+            self.card
+                .verify_pin_on_pad(ctx, EF_SIGN_PIN, &SIGN_PIN_FORMAT)
+                .and_then(|_| self.compute_signature(ctx, CertType::Sign, digest))
+        })
+    }
+
+    /// Changes the PIN for user authentication, entering them on the PIN pad.
+    pub fn change_auth_pin_on_pad(&self, ctx: Ctx) -> Result<(), card::Error> {
+        self.card.transaction_in(ctx, &DF_NAME, || {
+            self.card
+                .change_pin_on_pad(ctx, EF_AUTH_PIN, &AUTH_PIN_FORMAT)
+        })
+    }
+
+    /// Changes the PIN for signing, entering them on the PIN pad.
+    pub fn change_sign_pin_on_pad(&self, ctx: Ctx) -> Result<(), card::Error> {
+        self.card.transaction_in(ctx, &DF_NAME, || {
+            self.card
+                .change_pin_on_pad(ctx, EF_SIGN_PIN, &SIGN_PIN_FORMAT)
+        })
+    }
+}
+
+/// An asynchronous counterpart of [`CryptoAp`].
+#[cfg(feature = "async")]
+pub struct AsyncCryptoAp<T, Ctx>
+where
+    T: nfc::AsyncHandlerInCtx<Ctx>,
+    Ctx: Copy + Send + Sync + 'static,
+{
+    card: Arc<card::AsyncCard<T, Ctx>>,
+    digest_policy: Option<DigestPolicy>,
+    confirm_policy: Option<ConfirmPolicy>,
+    #[cfg(feature = "audit")]
+    auditor: Option<Auditor>,
+}
+
+#[cfg(feature = "async")]
+impl<T, Ctx> AsyncCryptoAp<T, Ctx>
+where
+    T: nfc::AsyncHandlerInCtx<Ctx>,
+    Ctx: Copy + Send + Sync + 'static,
+{
+    open_async!(
+        T,
+        Ctx,
+        DF_NAME,
+        digest_policy: None,
+        confirm_policy: None,
+        #[cfg(feature = "audit")]
+        auditor: None
+    );
+
+    /// Enables the strict mode, that signs only DigestInfo allowed by the policy.
+    /// Inputs are rejected before the PIN is sent.
+    /// This is opt-in, not to break existing callers that sign raw digests.
+    pub fn with_strict_mode(mut self, policy: DigestPolicy) -> Self {
+        self.digest_policy = Some(policy);
+        self
+    }
+
+    /// Confirms what is being signed with the user by the policy, before the PIN is sent.
+    pub fn with_confirm_policy(mut self, policy: ConfirmPolicy) -> Self {
+        self.confirm_policy = Some(policy);
+        self
+    }
+
+    /// Records every signature in the audit log, with the name of the caller.
+    /// Signatures are discarded if they cannot be recorded.
+    #[cfg(feature = "audit")]
+    pub fn with_audit_log(mut self, log: Arc<AuditLog>, caller: impl Into<String>) -> Self {
+        self.auditor = Some(Auditor::new(log, caller.into()));
+        self
     }
 
-    fn verify_auth_pin(&self, ctx: Ctx, pin: Vec<u8>) -> Result<(), card::Error> {
-        self.card.verify_pin(ctx, EF_AUTH_PIN, pin)
+    /// Reads a certificate of the type, unlocking with the PIN if required.
+    pub async fn read_certificate(
+        &self,
+        ctx: Ctx,
+        ty: CertType,
+        pin: SecretPin,
+    ) -> Result<Vec<u8>, card::Error> {
+        let mut card = self.card.lock_in(ctx, &DF_NAME).await?;
+        if ty.is_pin_required() {
+            SIGN_PIN_FORMAT.validate(pin.expose())?;
+            card.verify_pin(ctx, EF_SIGN_PIN, &pin).await?;
+        }
+
+        card.select_ef(ctx, ty.into_efid().into()).await?;
+        let size = card.read_der_size(ctx).await?;
+
+        card.read(ctx, Some(size)).await
     }
 
-    fn verify_sign_pin(&self, ctx: Ctx, pin: Vec<u8>) -> Result<(), card::Error> {
-        self.card.verify_pin(ctx, EF_SIGN_PIN, pin)
+    /// Computes a signature using the key-pair for authentication.
+    pub async fn auth(
+        &self,
+        ctx: Ctx,
+        pin: SecretPin,
+        digest: Vec<u8>,
+    ) -> Result<Vec<u8>, card::Error> {
+        self.check_digest(&digest)?;
+        self.confirm(Key::Auth, &digest)?;
+
+        let mut card = self.card.lock_in(ctx, &DF_NAME).await?;
+        AUTH_PIN_FORMAT.validate(pin.expose())?;
+        card.verify_pin(ctx, EF_AUTH_PIN, &pin).await?;
+
+        self.compute_signature(ctx, &mut card, CertType::Auth, digest)
+            .await
+    }
+
+    /// Computes a signature using the key-pair for signing.
+    pub async fn sign(
+        &self,
+        ctx: Ctx,
+        pin: SecretPin,
+        digest: Vec<u8>,
+    ) -> Result<Vec<u8>, card::Error> {
+        self.check_digest(&digest)?;
+        self.confirm(Key::Sign, &digest)?;
+
+        let mut card = self.card.lock_in(ctx, &DF_NAME).await?;
+        SIGN_PIN_FORMAT.validate(pin.expose())?;
+        card.verify_pin(ctx, EF_SIGN_PIN, &pin).await?;
+
+        self.compute_signature(ctx, &mut card, CertType::Sign, digest)
+            .await
+    }
+
+    /// Gets the status of PIN for user authentication.
+    pub async fn auth_pin_status(&self, ctx: Ctx) -> Result<u8, card::Error> {
+        let mut card = self.card.lock_in(ctx, &DF_NAME).await?;
+
+        card.pin_status(ctx, EF_AUTH_PIN).await
+    }
+
+    /// Gets the status of PIN for signing.
+    pub async fn sign_pin_status(&self, ctx: Ctx) -> Result<u8, card::Error> {
+        let mut card = self.card.lock_in(ctx, &DF_NAME).await?;
+
+        card.pin_status(ctx, EF_SIGN_PIN).await
+    }
+
+    fn check_digest(&self, digest: &[u8]) -> Result<(), card::Error> {
+        match &self.digest_policy {
+            Some(policy) => policy.check(digest).map(|_| ()).map_err(Into::into),
+            None => Ok(()),
+        }
+    }
+
+    fn confirm(&self, key: Key, digest: &[u8]) -> Result<(), card::Error> {
+        match &self.confirm_policy {
+            Some(policy) => policy.confirm(key, digest),
+            None => Ok(()),
+        }
+    }
+
+    /// Computes a signature using the key-pair of the certificate type, with the PIN verified.
+    /// The signature is recorded in the audit log if attached, blocking on writing the file.
+    async fn compute_signature(
+        &self,
+        ctx: Ctx,
+        card: &mut card::AsyncCardGuard<'_, T, Ctx>,
+        ty: CertType,
+        digest: Vec<u8>,
+    ) -> Result<Vec<u8>, card::Error> {
+        let ef = match ty {
+            CertType::Auth => EF_AUTH,
+            _ => EF_SIGN,
+        };
+
+        #[cfg(feature = "audit")]
+        if let Some(auditor) = &self.auditor {
+            let key = match ty {
+                CertType::Auth => Key::Auth,
+                _ => Key::Sign,
+            };
+
+            // The certificate is read before selecting the key-pair to sign with.
+            card.select_ef(ctx, ty.into_efid().into()).await?;
+            let size = card.read_der_size(ctx).await?;
+            let certificate = card.read(ctx, Some(size)).await?;
+
+            card.select_ef(ctx, ef.into()).await?;
+            let signature = card.sign(ctx, digest.clone()).await?;
+
+            auditor.record(key, &certificate, &digest)?;
+
+            return Ok(signature);
+        }
+
+        card.select_ef(ctx, ef.into()).await?;
+
+        card.sign(ctx, digest).await
     }
 }
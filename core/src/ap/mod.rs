@@ -4,19 +4,78 @@ pub mod crypto;
 pub mod support;
 pub mod surface;
 
-pub use self::crypto::CryptoAp;
-pub use self::support::SupportAp;
-pub use self::surface::SurfaceAp;
+pub use self::crypto::{CryptoAp, SharedCryptoAp};
+pub use self::support::{SharedSupportAp, SupportAp};
+pub use self::surface::{SharedSurfaceAp, SurfaceAp};
+
+#[cfg(feature = "async")]
+pub use self::crypto::AsyncCryptoAp;
+#[cfg(feature = "async")]
+pub use self::support::AsyncSupportAp;
+#[cfg(feature = "async")]
+pub use self::surface::AsyncSurfaceAp;
 
 macro_rules! open {
-    ($t: ty, $ctx: ty, $df: expr) => {
-        /// Opens the AP in the card by selecting the DF.
-        pub fn open(ctx: Ctx, card: Rc<crate::Card<$t, $ctx>>) -> Result<Self, crate::card::Error> {
-            let ap = Self { card };
+    ($ap: ident, $df: expr $(, $(#[$attr: meta])* $field: ident: $value: expr)*) => {
+        impl<T, Ctx> $ap<T, Ctx>
+        where
+            T: crate::nfc::HandlerInCtx<Ctx>,
+            Ctx: Copy,
+        {
+            /// Opens the AP in the card by selecting the DF.
+            pub fn open(
+                ctx: Ctx,
+                card: std::rc::Rc<crate::Card<T, Ctx>>,
+            ) -> Result<Self, crate::card::Error> {
+                let ap = Self {
+                    card,
+                    $($(#[$attr])* $field: $value,)*
+                };
+
+                ap.card.select_df(ctx, $df.into()).map(|_| ap)
+            }
+        }
+
+        impl<T, Ctx> $ap<T, Ctx, std::sync::Arc<crate::Card<T, Ctx>>>
+        where
+            T: crate::nfc::HandlerInCtx<Ctx>,
+            Ctx: Copy,
+        {
+            /// Opens the AP in the card shared across threads, by selecting the DF.
+            pub fn open_shared(
+                ctx: Ctx,
+                card: std::sync::Arc<crate::Card<T, Ctx>>,
+            ) -> Result<Self, crate::card::Error> {
+                let ap = Self {
+                    card,
+                    $($(#[$attr])* $field: $value,)*
+                };
 
-            ap.card.select_df(ctx, $df.into()).map(|_| ap)
+                ap.card.select_df(ctx, $df.into()).map(|_| ap)
+            }
         }
     };
 }
 
 pub(crate) use open;
+
+#[cfg(feature = "async")]
+macro_rules! open_async {
+    ($t: ty, $ctx: ty, $df: expr $(, $(#[$attr: meta])* $field: ident: $value: expr)*) => {
+        /// Opens the AP in the card by selecting the DF.
+        pub async fn open(
+            ctx: Ctx,
+            card: Arc<crate::card::AsyncCard<$t, $ctx>>,
+        ) -> Result<Self, crate::card::Error> {
+            card.lock().await.select_df(ctx, $df.into()).await?;
+
+            Ok(Self {
+                card,
+                $($(#[$attr])* $field: $value,)*
+            })
+        }
+    };
+}
+
+#[cfg(feature = "async")]
+pub(crate) use open_async;
@@ -1,13 +1,24 @@
+use std::ops::Deref;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::ap::open;
+#[cfg(feature = "async")]
+use crate::ap::open_async;
+use crate::pinpad::PinFormat;
+use crate::secret::SecretPin;
+use crate::session::Session;
 use crate::{card, nfc, Card};
 
-const DF_NAME: [u8; 10] = [0xD3, 0x92, 0x10, 0x00, 0x31, 0x00, 0x01, 0x01, 0x04, 0x08];
+pub(crate) const DF_NAME: [u8; 10] = [0xD3, 0x92, 0x10, 0x00, 0x31, 0x00, 0x01, 0x01, 0x04, 0x08];
 const EF_MY_NUMBER: [u8; 2] = [0x00, 0x01];
 const EF_ATTRIBUTES: [u8; 2] = [0x00, 0x02];
 const EF_PIN: [u8; 2] = [0x00, 0x11];
 
+/// Format of PIN for text filling support, in 4 digits.
+pub const PIN_FORMAT: PinFormat = PinFormat::new(4, 4);
+
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum Sex {
@@ -49,55 +60,181 @@ impl<'a> From<&'a [u8]> for Attributes {
     }
 }
 
-pub struct SupportAp<T, Ctx>
+pub struct SupportAp<T, Ctx, C = Rc<Card<T, Ctx>>>
 where
     T: nfc::HandlerInCtx<Ctx>,
     Ctx: Copy,
+    C: Deref<Target = Card<T, Ctx>>,
 {
-    card: Rc<Card<T, Ctx>>,
+    card: C,
 }
 
-impl<T, Ctx> SupportAp<T, Ctx>
+/// A [`SupportAp`] on the card shared across threads.
+pub type SharedSupportAp<T, Ctx> = SupportAp<T, Ctx, Arc<Card<T, Ctx>>>;
+
+open!(SupportAp, DF_NAME);
+
+impl<T, Ctx, C> SupportAp<T, Ctx, C>
 where
     T: nfc::HandlerInCtx<Ctx>,
     Ctx: Copy,
+    C: Deref<Target = Card<T, Ctx>> + Clone,
 {
-    open!(T, Ctx, DF_NAME);
-
     /// Reads the "My Number" from the card as DER-encoded ASN.1 data.
-    pub fn read_my_number_raw(&self, ctx: Ctx, pin: Vec<u8>) -> Result<Vec<u8>, card::Error> {
-        self.verify_pin(ctx, pin)
-            .and_then(|_| self.card.select_ef(ctx, EF_MY_NUMBER.into()))
-            .and_then(|_| self.card.read(ctx, Some(17)))
+    pub fn read_my_number_raw(&self, ctx: Ctx, pin: SecretPin) -> Result<Vec<u8>, card::Error> {
+        self.card.transaction_in(ctx, &DF_NAME, || {
+            self.verify_pin(ctx, pin)
+                .and_then(|_| self.card.select_ef(ctx, EF_MY_NUMBER.into()))
+                .and_then(|_| self.card.read(ctx, Some(17)))
+        })
     }
 
     /// Reads the "My Number" from the card as a string.
-    pub fn read_my_number(&self, ctx: Ctx, pin: Vec<u8>) -> Result<String, card::Error> {
+    pub fn read_my_number(&self, ctx: Ctx, pin: SecretPin) -> Result<String, card::Error> {
         self.read_my_number_raw(ctx, pin).map(|buf| {
             String::from_utf8_lossy(crate::der::Reader::new(&buf).read_auto()).to_string()
         })
     }
 
     /// Reads the text attributes from the card as DER-encoded ASN.1 data.
-    pub fn read_attributes_raw(&self, ctx: Ctx, pin: Vec<u8>) -> Result<Vec<u8>, card::Error> {
-        self.verify_pin(ctx, pin)
-            .and_then(|_| self.card.select_ef(ctx, EF_ATTRIBUTES.into()))
-            .and_then(|_| self.card.read_der_size(ctx))
-            .and_then(|size| self.card.read(ctx, Some(size)))
+    pub fn read_attributes_raw(&self, ctx: Ctx, pin: SecretPin) -> Result<Vec<u8>, card::Error> {
+        self.card.transaction_in(ctx, &DF_NAME, || {
+            self.verify_pin(ctx, pin)
+                .and_then(|_| self.card.select_ef(ctx, EF_ATTRIBUTES.into()))
+                .and_then(|_| self.card.read_der_size(ctx))
+                .and_then(|size| self.card.read(ctx, Some(size)))
+        })
     }
 
     /// Reads the text attributes from the card as decoded data.
-    pub fn read_attributes(&self, ctx: Ctx, pin: Vec<u8>) -> Result<Attributes, card::Error> {
+    pub fn read_attributes(&self, ctx: Ctx, pin: SecretPin) -> Result<Attributes, card::Error> {
         self.read_attributes_raw(ctx, pin)
             .map(|attrs| Attributes::from(attrs.as_slice()))
     }
 
     /// Gets the status of PIN.
     pub fn pin_status(&self, ctx: Ctx) -> Result<u8, card::Error> {
-        self.card.pin_status(ctx, EF_PIN)
+        self.card
+            .transaction_in(ctx, &DF_NAME, || self.card.pin_status(ctx, EF_PIN))
+    }
+
+    /// Verifies the PIN once, to read the data in the session without verifying again.
+    pub fn login(
+        &self,
+        ctx: Ctx,
+        pin: SecretPin,
+        timeout: Option<Duration>,
+    ) -> Result<Session<T, Ctx, C>, card::Error> {
+        PIN_FORMAT.validate(pin.expose())?;
+        Session::login(ctx, self.card.clone(), &DF_NAME, EF_PIN, &pin, timeout)
+    }
+
+    /// Reads the "My Number" from the card as a string, in the session.
+    pub fn read_my_number_with_session(
+        &self,
+        ctx: Ctx,
+        session: &Session<T, Ctx, C>,
+    ) -> Result<String, card::Error> {
+        let buf = session.run(ctx, &DF_NAME, EF_PIN, || {
+            self.card
+                .select_ef(ctx, EF_MY_NUMBER.into())
+                .and_then(|_| self.card.read(ctx, Some(17)))
+        })?;
+
+        Ok(String::from_utf8_lossy(crate::der::Reader::new(&buf).read_auto()).to_string())
+    }
+
+    /// Reads the text attributes from the card as decoded data, in the session.
+    pub fn read_attributes_with_session(
+        &self,
+        ctx: Ctx,
+        session: &Session<T, Ctx, C>,
+    ) -> Result<Attributes, card::Error> {
+        let attrs = session.run(ctx, &DF_NAME, EF_PIN, || {
+            self.card
+                .select_ef(ctx, EF_ATTRIBUTES.into())
+                .and_then(|_| self.card.read_der_size(ctx))
+                .and_then(|size| self.card.read(ctx, Some(size)))
+        })?;
+
+        Ok(Attributes::from(attrs.as_slice()))
+    }
+
+    fn verify_pin(&self, ctx: Ctx, pin: SecretPin) -> Result<(), card::Error> {
+        PIN_FORMAT.validate(pin.expose())?;
+        self.card.verify_pin(ctx, EF_PIN, &pin)
+    }
+}
+
+/// An asynchronous counterpart of [`SupportAp`].
+#[cfg(feature = "async")]
+pub struct AsyncSupportAp<T, Ctx>
+where
+    T: nfc::AsyncHandlerInCtx<Ctx>,
+    Ctx: Copy + Send + Sync + 'static,
+{
+    card: Arc<card::AsyncCard<T, Ctx>>,
+}
+
+#[cfg(feature = "async")]
+impl<T, Ctx> AsyncSupportAp<T, Ctx>
+where
+    T: nfc::AsyncHandlerInCtx<Ctx>,
+    Ctx: Copy + Send + Sync + 'static,
+{
+    open_async!(T, Ctx, DF_NAME);
+
+    /// Reads the "My Number" from the card as DER-encoded ASN.1 data.
+    pub async fn read_my_number_raw(
+        &self,
+        ctx: Ctx,
+        pin: SecretPin,
+    ) -> Result<Vec<u8>, card::Error> {
+        let mut card = self.card.lock_in(ctx, &DF_NAME).await?;
+        PIN_FORMAT.validate(pin.expose())?;
+        card.verify_pin(ctx, EF_PIN, &pin).await?;
+        card.select_ef(ctx, EF_MY_NUMBER.into()).await?;
+
+        card.read(ctx, Some(17)).await
+    }
+
+    /// Reads the "My Number" from the card as a string.
+    pub async fn read_my_number(&self, ctx: Ctx, pin: SecretPin) -> Result<String, card::Error> {
+        self.read_my_number_raw(ctx, pin).await.map(|buf| {
+            String::from_utf8_lossy(crate::der::Reader::new(&buf).read_auto()).to_string()
+        })
     }
 
-    fn verify_pin(&self, ctx: Ctx, pin: Vec<u8>) -> Result<(), card::Error> {
-        self.card.verify_pin(ctx, EF_PIN, pin)
+    /// Reads the text attributes from the card as DER-encoded ASN.1 data.
+    pub async fn read_attributes_raw(
+        &self,
+        ctx: Ctx,
+        pin: SecretPin,
+    ) -> Result<Vec<u8>, card::Error> {
+        let mut card = self.card.lock_in(ctx, &DF_NAME).await?;
+        PIN_FORMAT.validate(pin.expose())?;
+        card.verify_pin(ctx, EF_PIN, &pin).await?;
+        card.select_ef(ctx, EF_ATTRIBUTES.into()).await?;
+        let size = card.read_der_size(ctx).await?;
+
+        card.read(ctx, Some(size)).await
+    }
+
+    /// Reads the text attributes from the card as decoded data.
+    pub async fn read_attributes(
+        &self,
+        ctx: Ctx,
+        pin: SecretPin,
+    ) -> Result<Attributes, card::Error> {
+        self.read_attributes_raw(ctx, pin)
+            .await
+            .map(|attrs| Attributes::from(attrs.as_slice()))
+    }
+
+    /// Gets the status of PIN.
+    pub async fn pin_status(&self, ctx: Ctx) -> Result<u8, card::Error> {
+        let mut card = self.card.lock_in(ctx, &DF_NAME).await?;
+
+        card.pin_status(ctx, EF_PIN).await
     }
 }
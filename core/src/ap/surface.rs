@@ -1,23 +1,56 @@
 //! Card Surface AP: Application to provide information indicated on the card surface.
 
+use std::ops::Deref;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::ap::open;
+#[cfg(feature = "async")]
+use crate::ap::open_async;
+use crate::pinpad::{InvalidPin, PinFormat};
+use crate::secret::SecretPin;
 use crate::{card, nfc, Card};
 
-const DF_NAME: [u8; 10] = [0xD3, 0x92, 0x10, 0x00, 0x31, 0x00, 0x01, 0x01, 0x04, 0x02];
+pub(crate) const DF_NAME: [u8; 10] = [0xD3, 0x92, 0x10, 0x00, 0x31, 0x00, 0x01, 0x01, 0x04, 0x02];
 const EF_ID: [u8; 2] = [0x00, 0x02];
 const EF_PIN_A: [u8; 2] = [0x00, 0x13];
 const EF_PIN_B: [u8; 2] = [0x00, 0x12];
 
+/// Format of PIN type A, that is the My Number in 12 digits.
+pub const PIN_A_FORMAT: PinFormat = PinFormat::new(12, 12);
+
+/// Format of PIN type B, that is the DoB, the expiry year and the PIN in 14 digits.
+/// Note that the DoB in 'YYMMDD' format is validated in [`Pin::validate`] too.
+pub const PIN_B_FORMAT: PinFormat = PinFormat::new(14, 14);
+
 pub enum Pin {
     /// My Number (12 digits).
     /// Information from both front and back is available.
-    A(Vec<u8>),
+    A(SecretPin),
 
-    /// DoB in 'YYYYMMDD' format + Expiry date in 'YYYY' format + PIN (4 digits).
+    /// DoB in 'YYMMDD' format + Expiry date in 'YYYY' format + PIN (4 digits).
     /// Information from only front is available.
-    B(Vec<u8>),
+    B(SecretPin),
+}
+
+impl Pin {
+    /// Validates the PIN against the format of the type.
+    pub fn validate(&self) -> Result<(), InvalidPin> {
+        match self {
+            Self::A(pin) => PIN_A_FORMAT.validate(pin.expose()),
+            Self::B(pin) => {
+                PIN_B_FORMAT.validate(pin.expose())?;
+
+                // Month and day in the DoB, that is 'YYMMDD'.
+                let digits = pin.expose();
+                let number = |i: usize| (digits[i] - b'0') * 10 + (digits[i + 1] - b'0');
+                match (number(2), number(4)) {
+                    (1..=12, 1..=31) => Ok(()),
+                    _ => Err(InvalidPin::Date),
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -51,30 +84,39 @@ impl<'a> From<&'a [u8]> for Surface {
     }
 }
 
-pub struct SurfaceAp<T, Ctx>
+pub struct SurfaceAp<T, Ctx, C = Rc<Card<T, Ctx>>>
 where
     T: nfc::HandlerInCtx<Ctx>,
     Ctx: Copy,
+    C: Deref<Target = Card<T, Ctx>>,
 {
-    card: Rc<Card<T, Ctx>>,
+    card: C,
 }
 
-impl<T, Ctx> SurfaceAp<T, Ctx>
+/// A [`SurfaceAp`] on the card shared across threads.
+pub type SharedSurfaceAp<T, Ctx> = SurfaceAp<T, Ctx, Arc<Card<T, Ctx>>>;
+
+open!(SurfaceAp, DF_NAME);
+
+impl<T, Ctx, C> SurfaceAp<T, Ctx, C>
 where
     T: nfc::HandlerInCtx<Ctx>,
     Ctx: Copy,
+    C: Deref<Target = Card<T, Ctx>>,
 {
-    open!(T, Ctx, DF_NAME);
-
     /// Reads the surface information as DER-encoded ASN.1 data.
     pub fn read_surface_raw(&self, ctx: Ctx, pin: Pin) -> Result<Vec<u8>, card::Error> {
-        match pin {
-            Pin::A(pin) => self.verify_pin_a(ctx, pin),
-            Pin::B(pin) => self.verify_pin_b(ctx, pin),
-        }
-        .and_then(|_| self.card.select_ef(ctx, EF_ID.into()))
-        .and_then(|_| self.card.read_der_size(ctx))
-        .and_then(|size| self.card.read(ctx, Some(size)))
+        pin.validate()?;
+
+        self.card.transaction_in(ctx, &DF_NAME, || {
+            match pin {
+                Pin::A(pin) => self.verify_pin_a(ctx, pin),
+                Pin::B(pin) => self.verify_pin_b(ctx, pin),
+            }
+            .and_then(|_| self.card.select_ef(ctx, EF_ID.into()))
+            .and_then(|_| self.card.read_der_size(ctx))
+            .and_then(|size| self.card.read(ctx, Some(size)))
+        })
     }
 
     /// Reads the surface information as decoded data.
@@ -85,19 +127,95 @@ where
 
     /// Gets the status of PIN type A.
     pub fn pin_a_status(&self, ctx: Ctx) -> Result<u8, card::Error> {
-        self.card.pin_status(ctx, EF_PIN_A)
+        self.card
+            .transaction_in(ctx, &DF_NAME, || self.card.pin_status(ctx, EF_PIN_A))
     }
 
     /// Gets the status of PIN type B.
     pub fn pin_b_status(&self, ctx: Ctx) -> Result<u8, card::Error> {
-        self.card.pin_status(ctx, EF_PIN_B)
+        self.card
+            .transaction_in(ctx, &DF_NAME, || self.card.pin_status(ctx, EF_PIN_B))
+    }
+
+    fn verify_pin_a(&self, ctx: Ctx, pin: SecretPin) -> Result<(), card::Error> {
+        self.card.verify_pin(ctx, EF_PIN_A, &pin)
+    }
+
+    fn verify_pin_b(&self, ctx: Ctx, pin: SecretPin) -> Result<(), card::Error> {
+        self.card.verify_pin(ctx, EF_PIN_B, &pin)
+    }
+}
+
+/// An asynchronous counterpart of [`SurfaceAp`].
+#[cfg(feature = "async")]
+pub struct AsyncSurfaceAp<T, Ctx>
+where
+    T: nfc::AsyncHandlerInCtx<Ctx>,
+    Ctx: Copy + Send + Sync + 'static,
+{
+    card: Arc<card::AsyncCard<T, Ctx>>,
+}
+
+#[cfg(feature = "async")]
+impl<T, Ctx> AsyncSurfaceAp<T, Ctx>
+where
+    T: nfc::AsyncHandlerInCtx<Ctx>,
+    Ctx: Copy + Send + Sync + 'static,
+{
+    open_async!(T, Ctx, DF_NAME);
+
+    /// Reads the surface information as DER-encoded ASN.1 data.
+    pub async fn read_surface_raw(&self, ctx: Ctx, pin: Pin) -> Result<Vec<u8>, card::Error> {
+        pin.validate()?;
+
+        let mut card = self.card.lock_in(ctx, &DF_NAME).await?;
+        match pin {
+            Pin::A(pin) => card.verify_pin(ctx, EF_PIN_A, &pin).await?,
+            Pin::B(pin) => card.verify_pin(ctx, EF_PIN_B, &pin).await?,
+        }
+
+        card.select_ef(ctx, EF_ID.into()).await?;
+        let size = card.read_der_size(ctx).await?;
+
+        card.read(ctx, Some(size)).await
+    }
+
+    /// Reads the surface information as decoded data.
+    pub async fn read_surface(&self, ctx: Ctx, pin: Pin) -> Result<Surface, card::Error> {
+        self.read_surface_raw(ctx, pin)
+            .await
+            .map(|info| Surface::from(info.as_slice()))
+    }
+
+    /// Gets the status of PIN type A.
+    pub async fn pin_a_status(&self, ctx: Ctx) -> Result<u8, card::Error> {
+        let mut card = self.card.lock_in(ctx, &DF_NAME).await?;
+
+        card.pin_status(ctx, EF_PIN_A).await
     }
 
-    fn verify_pin_a(&self, ctx: Ctx, pin: Vec<u8>) -> Result<(), card::Error> {
-        self.card.verify_pin(ctx, EF_PIN_A, pin)
+    /// Gets the status of PIN type B.
+    pub async fn pin_b_status(&self, ctx: Ctx) -> Result<u8, card::Error> {
+        let mut card = self.card.lock_in(ctx, &DF_NAME).await?;
+
+        card.pin_status(ctx, EF_PIN_B).await
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_pin() {
+        let pin = |s: &str| SecretPin::new(s.as_bytes().to_vec());
 
-    fn verify_pin_b(&self, ctx: Ctx, pin: Vec<u8>) -> Result<(), card::Error> {
-        self.card.verify_pin(ctx, EF_PIN_B, pin)
+        assert!(Pin::A(pin("123456789012")).validate().is_ok());
+        assert!(Pin::A(pin("12345678901")).validate().is_err());
+        assert!(Pin::B(pin("90123120301234")).validate().is_ok());
+        assert!(matches!(
+            Pin::B(pin("90133120301234")).validate(),
+            Err(InvalidPin::Date),
+        ));
     }
 }
@@ -0,0 +1,271 @@
+//! Parsing ATR (Answer To Reset) of cards, as defined in ISO/IEC 7816-3.
+//! Contactless cards on PC/SC readers have ATRs constructed by the reader, as defined in PC/SC part 3.
+
+/// RID (Registered Application Provider Identifier) of PC/SC, in ATRs of contactless storage cards.
+const PCSC_RID: [u8; 5] = [0xA0, 0x00, 0x00, 0x03, 0x06];
+
+/// Standard of contactless storage cards, that is FeliCa.
+const STANDARD_FELICA: u8 = 0x11;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The ATR is shorter than indicated")]
+    TooShort,
+
+    #[error("Invalid initial character: {0:02X}")]
+    InvalidTs(u8),
+
+    #[error("The check byte does not match")]
+    ChecksumMismatch,
+}
+
+/// Convention of the bits, indicated by the initial character (TS).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Convention {
+    Direct,
+    Inverse,
+}
+
+/// Interface bytes in a group, that indicates protocol parameters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InterfaceBytes {
+    pub ta: Option<u8>,
+    pub tb: Option<u8>,
+    pub tc: Option<u8>,
+    pub td: Option<u8>,
+}
+
+/// Kind of the card guessed from the ATR.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum CardKind {
+    /// Contact card.
+    Contact,
+
+    /// ISO/IEC 14443-4 contactless card, such as the My Number card on NFC readers.
+    Iso14443,
+
+    /// FeliCa contactless card, such as transit IC cards.
+    Felica,
+
+    /// Other contactless storage cards, such as MIFARE Classic.
+    Storage,
+}
+
+/// A parsed ATR.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Atr {
+    raw: Vec<u8>,
+    pub convention: Convention,
+    pub interface_bytes: Vec<InterfaceBytes>,
+    pub historical_bytes: Vec<u8>,
+    pub tck: Option<u8>,
+}
+
+impl Atr {
+    /// Parses the ATR, verifying the check byte if present.
+    pub fn parse(buf: &[u8]) -> Result<Self, Error> {
+        let mut bytes = buf.iter().copied();
+        let mut next = || bytes.next().ok_or(Error::TooShort);
+
+        let convention = match next()? {
+            0x3B => Convention::Direct,
+            0x3F => Convention::Inverse,
+            ts => return Err(Error::InvalidTs(ts)),
+        };
+
+        let t0 = next()?;
+        let mut indicator = t0 >> 4;
+        let mut interface_bytes = vec![];
+
+        loop {
+            let mut group = InterfaceBytes::default();
+            let mut read = |bit: u8| match indicator & bit {
+                0 => Ok(None),
+                _ => next().map(Some),
+            };
+
+            group.ta = read(0x1)?;
+            group.tb = read(0x2)?;
+            group.tc = read(0x4)?;
+            group.td = read(0x8)?;
+            interface_bytes.push(group);
+
+            match group.td {
+                Some(td) => indicator = td >> 4,
+                None => break,
+            }
+        }
+
+        let historical_bytes = (0..t0 & 0x0F)
+            .map(|_| next())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut atr = Self {
+            raw: vec![],
+            convention,
+            interface_bytes,
+            historical_bytes,
+            tck: None,
+        };
+
+        // The check byte is present unless only T=0 is indicated.
+        if atr.protocols().iter().any(|t| *t != 0) {
+            atr.tck = Some(next()?);
+
+            let len = buf.len() - bytes.len();
+            if buf[1..len].iter().fold(0, |acc, b| acc ^ b) != 0 {
+                return Err(Error::ChecksumMismatch);
+            }
+        }
+
+        let len = buf.len() - bytes.len();
+        atr.raw = buf[..len].to_vec();
+
+        Ok(atr)
+    }
+
+    /// Gets the ATR as is.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Gets the protocols indicated, such as 0 for T=0 and 1 for T=1.
+    /// T=0 is implicitly indicated if nothing is indicated.
+    pub fn protocols(&self) -> Vec<u8> {
+        let mut protocols = vec![];
+        for t in self
+            .interface_bytes
+            .iter()
+            .filter_map(|i| i.td.map(|td| td & 0x0F))
+        {
+            if !protocols.contains(&t) {
+                protocols.push(t);
+            }
+        }
+
+        if protocols.is_empty() {
+            protocols.push(0);
+        }
+
+        protocols
+    }
+
+    /// Gets the indices of the clock rate conversion factor Fi and the baud rate adjustment factor
+    /// Di, from TA1.
+    pub fn fi_di(&self) -> (u8, u8) {
+        match self.interface_bytes.first().and_then(|i| i.ta) {
+            Some(ta1) => (ta1 >> 4, ta1 & 0x0F),
+            None => (1, 1),
+        }
+    }
+
+    /// Gets the extra guard time N, from TC1.
+    pub fn extra_guard_time(&self) -> u8 {
+        self.interface_bytes
+            .first()
+            .and_then(|i| i.tc)
+            .unwrap_or_default()
+    }
+
+    /// Guesses the kind of the card.
+    pub fn kind(&self) -> CardKind {
+        // Contactless cards have the ATR that indicates T=0 then T=1 without parameters.
+        let is_contactless = matches!(
+            self.interface_bytes.as_slice(),
+            [
+                InterfaceBytes {
+                    ta: None,
+                    tb: None,
+                    tc: None,
+                    td: Some(0x80),
+                },
+                InterfaceBytes {
+                    ta: None,
+                    tb: None,
+                    tc: None,
+                    td: Some(0x01),
+                },
+                ..
+            ],
+        );
+
+        if !is_contactless {
+            return CardKind::Contact;
+        }
+
+        // Storage cards have the historical bytes of the application identifier, in the RID of
+        // PC/SC, the standard (SS), the card name (NN) and RFU.
+        match self.historical_bytes.as_slice() {
+            [0x80, 0x4F, _, a, b, c, d, e, standard, ..] if [*a, *b, *c, *d, *e] == PCSC_RID => {
+                match *standard {
+                    STANDARD_FELICA => CardKind::Felica,
+                    _ => CardKind::Storage,
+                }
+            }
+            _ => CardKind::Iso14443,
+        }
+    }
+
+    /// Determines whether the card can be a My Number card, that is an ISO/IEC 14443-4 card on NFC
+    /// readers or a contact card.
+    /// Use [`crate::Card::identify`] to confirm by probing the APs.
+    pub fn may_be_my_number_card(&self) -> bool {
+        matches!(self.kind(), CardKind::Contact | CardKind::Iso14443)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contact() {
+        let atr = Atr::parse(&[0x3B, 0x02, 0x14, 0x50]).unwrap();
+
+        assert_eq!(Convention::Direct, atr.convention);
+        assert_eq!(vec![0x14, 0x50], atr.historical_bytes);
+        assert_eq!(vec![0], atr.protocols());
+        assert_eq!(None, atr.tck);
+        assert_eq!(CardKind::Contact, atr.kind());
+    }
+
+    #[test]
+    fn test_iso14443() {
+        let buf = [
+            0x3B, 0x88, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0xB3, 0x81, 0x81, 0x00, 0xBA,
+        ];
+        let atr = Atr::parse(&buf).unwrap();
+
+        assert_eq!(vec![0, 1], atr.protocols());
+        assert_eq!(Some(0xBA), atr.tck);
+        assert_eq!(CardKind::Iso14443, atr.kind());
+        assert!(atr.may_be_my_number_card());
+        assert_eq!(&buf, atr.as_bytes());
+    }
+
+    #[test]
+    fn test_felica() {
+        let atr = Atr::parse(&[
+            0x3B, 0x8F, 0x80, 0x01, 0x80, 0x4F, 0x0C, 0xA0, 0x00, 0x00, 0x03, 0x06, 0x11, 0x00,
+            0x3B, 0x00, 0x00, 0x00, 0x00, 0x42,
+        ])
+        .unwrap();
+
+        assert_eq!(CardKind::Felica, atr.kind());
+        assert!(!atr.may_be_my_number_card());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(matches!(
+            Atr::parse(&[0x3B, 0x88, 0x80, 0x01, 0x00]),
+            Err(Error::TooShort),
+        ));
+        assert!(matches!(
+            Atr::parse(&[0x3B, 0x81, 0x80, 0x01, 0x00, 0x01]),
+            Err(Error::ChecksumMismatch),
+        ));
+        assert!(matches!(Atr::parse(&[0x00]), Err(Error::InvalidTs(0x00))));
+    }
+}
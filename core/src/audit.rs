@@ -0,0 +1,308 @@
+//! Append-only audit log of signatures computed by the card, to be kept for compliance.
+//!
+//! Each entry is a line of tab-separated fields: the sequence number, the timestamp in UNIX
+//! seconds, the key-pair, the serial of the certificate, the hash algorithm, the digest, the caller,
+//! the hash of the previous entry and the hash of this entry.
+//! The hash is SHA-256 of the line except the last field, so modifying or deleting any entry breaks
+//! the chain after them.
+//! Truncating the last entries cannot be detected from the log alone, so keep the [`Head`] returned
+//! by [`verify`] elsewhere to compare later.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use crate::ap::crypto::Key;
+use crate::der::read_tlv;
+use crate::digest_info::{DigestPolicy, HashAlgorithm};
+use crate::sync::lock;
+
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_VERSION: u8 = 0xA0;
+
+/// Number of fields in an entry, except the hash of the entry.
+const BODY_FIELDS: usize = 8;
+
+/// Recorded in place of the hash algorithm, if the input was not DigestInfo.
+const RAW: &str = "raw";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("I/O error occurred on the audit log: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("The audit log is malformed at line {0}")]
+    Malformed(usize),
+
+    #[error("The chain of the audit log is broken at line {0}")]
+    Broken(usize),
+
+    #[error("The certificate is malformed, so the serial cannot be recorded")]
+    InvalidCertificate,
+}
+
+/// A signature to be recorded in the audit log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub key: Key,
+
+    /// Serial number of the certificate of the key-pair.
+    pub serial: Vec<u8>,
+
+    /// Hash algorithm of the digest, or `None` if the input was not DigestInfo.
+    pub algorithm: Option<HashAlgorithm>,
+
+    /// Digest of the document, or the input as is if it was not DigestInfo.
+    pub digest: Vec<u8>,
+
+    /// Name of the user or the application that requested the signature.
+    pub caller: String,
+}
+
+impl Record {
+    /// Builds a record from the certificate of the key-pair and the input that was signed.
+    pub fn new(
+        key: Key,
+        certificate: &[u8],
+        input: &[u8],
+        caller: impl Into<String>,
+    ) -> Result<Self, Error> {
+        let serial = certificate_serial(certificate).ok_or(Error::InvalidCertificate)?;
+        let (algorithm, digest) = match DigestPolicy::new(&HashAlgorithm::ALL).check(input) {
+            Ok(info) => (Some(info.algorithm), info.digest),
+            Err(_) => (None, input),
+        };
+
+        Ok(Self {
+            key,
+            serial: serial.to_vec(),
+            algorithm,
+            digest: digest.to_vec(),
+            caller: caller.into(),
+        })
+    }
+}
+
+/// Head of the chain, to detect the log being truncated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Head {
+    /// Number of the entries.
+    pub len: u64,
+
+    /// Hash of the last entry, or zeros if empty.
+    pub hash: [u8; 32],
+}
+
+/// An audit log in a file, that entries are only appended to.
+/// Only a writer can append at once, as entries appended by other processes break the chain.
+pub struct AuditLog {
+    state: Mutex<(File, Head)>,
+}
+
+impl AuditLog {
+    /// Opens the log in the file, creating if not exists.
+    /// Fails if the chain is already broken, not to append to the tampered log.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let head = match File::open(path) {
+            Ok(file) => verify(BufReader::new(file))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Head::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            state: Mutex::new((file, head)),
+        })
+    }
+
+    /// Appends the record to the log, syncing to the disk before returning the new head.
+    pub fn append(&self, record: &Record) -> Result<Head, Error> {
+        let mut state = lock(&self.state);
+        let (file, head) = &mut *state;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let body = [
+            head.len.to_string(),
+            timestamp.to_string(),
+            record.key.to_string(),
+            hex::encode(&record.serial),
+            record
+                .algorithm
+                .map(|a| a.to_string())
+                .unwrap_or_else(|| RAW.to_string()),
+            hex::encode(&record.digest),
+            // Separators in the caller would break the fields.
+            record.caller.replace(|c: char| c.is_control(), " "),
+            hex::encode(head.hash),
+        ]
+        .join("\t");
+
+        let hash = hash(&body);
+        file.write_all(format!("{}\t{}\n", body, hex::encode(hash)).as_bytes())?;
+        file.sync_data()?;
+
+        *head = Head {
+            len: head.len + 1,
+            hash,
+        };
+
+        Ok(*head)
+    }
+
+    /// Gets the head of the chain, including the entries appended.
+    pub fn head(&self) -> Head {
+        lock(&self.state).1
+    }
+}
+
+/// Audit log attached to an AP, with the caller to be recorded.
+pub(crate) struct Auditor {
+    log: Arc<AuditLog>,
+    caller: String,
+}
+
+impl Auditor {
+    pub(crate) fn new(log: Arc<AuditLog>, caller: String) -> Self {
+        Self { log, caller }
+    }
+
+    /// Records the signature computed by the key-pair of the certificate.
+    pub(crate) fn record(&self, key: Key, certificate: &[u8], input: &[u8]) -> Result<(), Error> {
+        let record = Record::new(key, certificate, input, self.caller.as_str())?;
+
+        self.log.append(&record).map(|_| ())
+    }
+}
+
+/// Verifies the chain of entries in the log, returning the head.
+pub fn verify(reader: impl BufRead) -> Result<Head, Error> {
+    let mut head = Head::default();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let malformed = || Error::Malformed(i + 1);
+
+        let (body, actual) = line.rsplit_once('\t').ok_or_else(malformed)?;
+        let fields = body.split('\t').collect::<Vec<_>>();
+        if fields.len() != BODY_FIELDS {
+            return Err(malformed());
+        }
+
+        let sequence = fields[0].parse::<u64>().map_err(|_| malformed())?;
+        let previous = decode_hash(fields[BODY_FIELDS - 1]).ok_or_else(malformed)?;
+        let actual = decode_hash(actual).ok_or_else(malformed)?;
+        if sequence != head.len || previous != head.hash || actual != hash(body) {
+            return Err(Error::Broken(i + 1));
+        }
+
+        head = Head {
+            len: head.len + 1,
+            hash: actual,
+        };
+    }
+
+    Ok(head)
+}
+
+fn hash(body: &str) -> [u8; 32] {
+    Sha256::digest(body.as_bytes()).into()
+}
+
+fn decode_hash(hex: &str) -> Option<[u8; 32]> {
+    hex::decode(hex).ok()?.try_into().ok()
+}
+
+/// Extracts the serial number from the DER-encoded X.509 certificate.
+fn certificate_serial(certificate: &[u8]) -> Option<&[u8]> {
+    let (certificate, _) = read_tlv(certificate, TAG_SEQUENCE)?;
+    let (tbs, _) = read_tlv(certificate, TAG_SEQUENCE)?;
+
+    // The version is omitted in v1 certificates.
+    let tbs = match read_tlv(tbs, TAG_VERSION) {
+        Some((_, rest)) => rest,
+        None => tbs,
+    };
+
+    read_tlv(tbs, TAG_INTEGER).map(|(serial, _)| serial)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Beginning of a certificate, enough to extract the serial.
+    const CERTIFICATE: [u8; 15] = [
+        0x30, 0x82, 0x00, 0x0B, 0x30, 0x09, 0xA0, 0x03, 0x02, 0x01, 0x02, 0x02, 0x02, 0x12, 0x34,
+    ];
+
+    fn record(caller: &str) -> Record {
+        let mut input = vec![
+            0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x01, 0x05, 0x00, 0x04, 0x20,
+        ];
+        input.extend_from_slice(&[0xAB; 32]);
+
+        Record::new(Key::Sign, &CERTIFICATE, &input, caller).unwrap()
+    }
+
+    #[test]
+    fn test_record() {
+        let record = record("test");
+        assert_eq!(vec![0x12, 0x34], record.serial);
+        assert_eq!(Some(HashAlgorithm::Sha256), record.algorithm);
+        assert_eq!(vec![0xAB; 32], record.digest);
+
+        let record = Record::new(Key::Auth, &CERTIFICATE, b"raw input", "test").unwrap();
+        assert_eq!(None, record.algorithm);
+        assert_eq!(b"raw input".to_vec(), record.digest);
+
+        assert!(matches!(
+            Record::new(Key::Auth, &CERTIFICATE[..10], b"raw input", "test"),
+            Err(Error::InvalidCertificate),
+        ));
+    }
+
+    #[test]
+    fn test_append_and_verify() {
+        let path = std::env::temp_dir().join(format!("jpki-audit-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let log = AuditLog::open(&path).unwrap();
+        for caller in ["first", "second\tcaller", "third"] {
+            log.append(&record(caller)).unwrap();
+        }
+
+        let head = log.head();
+        assert_eq!(3, head.len);
+        drop(log);
+
+        let content = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(head, verify(Cursor::new(&content)).unwrap());
+
+        let lines = content.lines().collect::<Vec<_>>();
+        let deleted = [lines[0], lines[2]].join("\n");
+        assert!(matches!(
+            verify(Cursor::new(deleted)),
+            Err(Error::Broken(2))
+        ));
+
+        let modified = content.replacen("first", "forged", 1);
+        assert!(matches!(
+            verify(Cursor::new(modified)),
+            Err(Error::Broken(1))
+        ));
+    }
+}
@@ -1,18 +1,32 @@
 use std::fmt::{Debug, Display, Formatter};
 use std::marker::PhantomData;
+use std::sync::Mutex;
 
 use apdu::core::HandleError;
 use apdu::{command, Command, Response};
+use zeroize::Zeroizing;
 
+use crate::ap::{crypto, support, surface};
 use crate::der::entire_size_from_partial;
+use crate::digest_info;
 use crate::nfc;
+#[cfg(feature = "tracing")]
+use crate::pcap;
+use crate::pinpad::{InvalidPin, PinFormat};
+use crate::secret::SecretPin;
+use crate::sync::{lock, ReentrantLock};
 
 const SELECT_P1_DF: u8 = 0x04;
 const SELECT_P1_EF: u8 = 0x02;
 const SELECT_P2: u8 = 0x0C;
 
+const VERIFY_INS: u8 = 0x20;
 const VERIFY_P2: u8 = 0x80;
 
+const CHANGE_INS: u8 = 0x24;
+const CHANGE_P1: u8 = 0x01;
+const CHANGE_P2: u8 = 0x80;
+
 const SIGN_CLA: u8 = 0x80;
 const SIGN_INS: u8 = 0x2A;
 const SIGN_P1: u8 = 0x00;
@@ -24,7 +38,26 @@ pub enum Error {
     Apdu(#[from] nfc::Error),
 
     /// Unexpected error occurred on the device.
-    Device(Box<dyn Display>),
+    Device(String),
+
+    /// PIN is malformed, that was rejected before sent to the card.
+    InvalidPin(#[from] InvalidPin),
+
+    /// PIN was not sent, as few retries remain and the caller did not confirm.
+    FewRetriesLeft(u8),
+
+    /// Session has ended, so the PIN needs to be verified again.
+    SessionEnded,
+
+    /// Input to be signed was rejected in the strict mode.
+    InvalidDigest(#[from] digest_info::Error),
+
+    /// Signing was not confirmed by the user, so the PIN was not sent.
+    NotConfirmed,
+
+    /// Signature was discarded, as it could not be recorded in the audit log.
+    #[cfg(feature = "audit")]
+    Audit(#[from] crate::audit::Error),
 }
 
 impl Debug for Error {
@@ -33,22 +66,115 @@ impl Debug for Error {
     }
 }
 
+impl From<HandleError> for Error {
+    fn from(e: HandleError) -> Self {
+        match e {
+            HandleError::Nfc(e) => Error::Device(e.to_string()),
+            HandleError::NotEnoughBuffer(len) => {
+                Error::Device(format!("Not enough buffer: {} octets required", len))
+            }
+        }
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::Apdu(e) => Display::fmt(e, f),
-            Error::Device(e) => e.fmt(f),
+            Error::Device(e) => Display::fmt(e, f),
+            Error::InvalidPin(e) => Display::fmt(e, f),
+            Error::FewRetriesLeft(remaining) => write!(
+                f,
+                "Refused to send the PIN, as only {} retries remain",
+                remaining
+            ),
+            Error::SessionEnded => write!(f, "The session has ended, verify the PIN again"),
+            Error::InvalidDigest(e) => Display::fmt(e, f),
+            Error::NotConfirmed => write!(f, "The signing was not confirmed"),
+            #[cfg(feature = "audit")]
+            Error::Audit(e) => Display::fmt(e, f),
         }
     }
 }
 
-/// An adapter to communicate with the card through the delegate
+/// Policy to refuse sending PINs when few retries remain, not to block them by repeated mistakes.
+/// The remaining retries are queried before every VERIFY with the PIN.
+pub struct RetryGuard {
+    limit: u8,
+    confirm: Box<dyn Fn(u8) -> bool + Send + Sync>,
+}
+
+impl RetryGuard {
+    /// Refuses to send PINs when the remaining retries are the limit or less.
+    pub fn new(limit: u8) -> Self {
+        Self {
+            limit,
+            confirm: Box::new(|_| false),
+        }
+    }
+
+    /// Asks the function with the remaining retries whether to send the PIN anyway.
+    pub fn with_confirmation<F>(mut self, confirm: F) -> Self
+    where
+        F: Fn(u8) -> bool + Send + Sync + 'static,
+    {
+        self.confirm = Box::new(confirm);
+        self
+    }
+
+    /// Checks the result of VERIFY without any PINs, that tells the remaining retries.
+    fn check(&self, status: Result<(), Error>) -> Result<(), Error> {
+        match status {
+            // Verified already, so no retries are consumed.
+            Ok(_) => Ok(()),
+            Err(Error::Apdu(nfc::Error::VerifyFailed(remaining)))
+                if remaining <= self.limit && !(self.confirm)(remaining) =>
+            {
+                Err(Error::FewRetriesLeft(remaining))
+            }
+            Err(Error::Apdu(nfc::Error::VerifyFailed(_))) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// APs found in the card by probing their DF names.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Identity {
+    pub crypto_ap: bool,
+    pub surface_ap: bool,
+    pub support_ap: bool,
+}
+
+impl Identity {
+    /// Determines whether the card is a My Number card, that has all of the APs.
+    pub fn is_my_number_card(&self) -> bool {
+        self.crypto_ap && self.surface_ap && self.support_ap
+    }
+}
+
+/// Runs the function in a transaction of the delegate.
+type TransactionFn<T, Ctx> = fn(&T, Ctx, &mut dyn FnMut()) -> Result<(), HandleError>;
+
+/// Determines whether the card has been reset, through the delegate.
+type TakeResetFn<T> = fn(&T) -> bool;
+
+/// An adapter to communicate with the card through the delegate.
+/// Operations are serialized, so the adapter can be shared across threads in [`std::sync::Arc`]
+/// if the delegate is [`Send`] and [`Sync`], with the APs opened by `open_shared`.
 pub struct Card<T, Ctx>
 where
     T: nfc::HandlerInCtx<Ctx>,
     Ctx: Copy,
 {
     delegate: Box<T>,
+    lock: ReentrantLock,
+    selected_df: Mutex<Option<Vec<u8>>>,
+    sessions: Mutex<Vec<u64>>,
+    retry_guard: Option<RetryGuard>,
+    transaction: Option<TransactionFn<T, Ctx>>,
+    take_reset: Option<TakeResetFn<T>>,
     _ctx: PhantomData<Ctx>,
 }
 
@@ -61,14 +187,47 @@ where
     pub fn new(delegate: Box<T>) -> Self {
         Self {
             delegate,
+            lock: ReentrantLock::default(),
+            selected_df: Mutex::new(None),
+            sessions: Mutex::new(vec![]),
+            retry_guard: None,
+            transaction: None,
+            take_reset: None,
             _ctx: PhantomData,
         }
     }
 
+    /// Guards the PINs from being blocked, that is opt-in.
+    pub fn with_retry_guard(mut self, guard: RetryGuard) -> Self {
+        self.retry_guard = Some(guard);
+        self
+    }
+
+    /// Runs composite operations in transactions of the delegate, such as ones in PC/SC.
+    /// Without this, operations are serialized only in this adapter.
+    /// Resets of the card reported by the delegate end the sessions and forget the DF selected.
+    pub fn with_transactions(mut self) -> Self
+    where
+        T: nfc::Transactional<Ctx>,
+    {
+        self.transaction = Some(|delegate, ctx, f| delegate.transaction(ctx, f));
+        self.take_reset = Some(|delegate| delegate.take_reset());
+        self
+    }
+
     /// Selects a DF with their name.
+    /// Sessions end here, as selecting a DF resets the security status on the card.
     pub fn select_df(&self, ctx: Ctx, name: Vec<u8>) -> Result<(), Error> {
-        self.handle(ctx, command::select_file(SELECT_P1_DF, SELECT_P2, &name))
-            .map(|_| ())
+        let _guard = self.lock.acquire();
+        lock(&self.sessions).clear();
+
+        let result = self
+            .handle(ctx, command::select_file(SELECT_P1_DF, SELECT_P2, &name))
+            .map(|_| ());
+
+        *lock(&self.selected_df) = result.as_ref().ok().map(|_| name);
+
+        result
     }
 
     /// Selects a EF with their name.
@@ -113,8 +272,8 @@ where
     }
 
     /// Verifies the PIN.
-    pub fn verify(&self, ctx: Ctx, pin: Vec<u8>) -> Result<(), Error> {
-        self.handle(ctx, command::verify(VERIFY_P2, &pin))
+    pub fn verify(&self, ctx: Ctx, pin: &SecretPin) -> Result<(), Error> {
+        self.handle(ctx, command::verify(VERIFY_P2, pin.expose()))
             .map(|_| ())
     }
 
@@ -127,22 +286,83 @@ where
     }
 
     /// Selects a EF then verifies the pin using the EF.
-    pub fn verify_pin(&self, ctx: Ctx, ef: [u8; 2], pin: Vec<u8>) -> Result<(), Error> {
-        self.select_ef(ctx, ef.into())
-            .and_then(|_| self.verify(ctx, pin))
+    /// With the retry guard, the remaining retries are checked before sending the PIN.
+    pub fn verify_pin(&self, ctx: Ctx, ef: [u8; 2], pin: &SecretPin) -> Result<(), Error> {
+        let _guard = self.lock.acquire();
+        self.select_ef(ctx, ef.into())?;
+
+        if let (Some(retry_guard), false) = (&self.retry_guard, pin.is_empty()) {
+            retry_guard.check(self.verify(ctx, &SecretPin::default()))?;
+        }
+
+        self.verify(ctx, pin)
     }
 
     pub fn pin_status(&self, ctx: Ctx, ef: [u8; 2]) -> Result<u8, Error> {
-        match self
-            .select_ef(ctx, ef.into())
-            .and_then(|_| self.verify(ctx, vec![]))
-        {
+        match self.transaction(ctx, || {
+            self.select_ef(ctx, ef.into())
+                .and_then(|_| self.verify(ctx, &SecretPin::default()))
+        }) {
             Ok(_) => Ok(0),
             Err(Error::Apdu(nfc::Error::VerifyFailed(count))) => Ok(count),
             Err(e) => Err(e),
         }
     }
 
+    /// Probes the APs of My Number card by selecting their DFs.
+    /// Cards that reject the SELECT commands, such as Juki cards, are identified without any APs.
+    /// Note that the DF selected before is lost.
+    pub fn identify(&self, ctx: Ctx) -> Result<Identity, Error> {
+        self.transaction(ctx, || {
+            let probe = |name: &[u8]| match self.select_df(ctx, name.into()) {
+                Ok(_) => Ok(true),
+                Err(Error::Apdu(_)) => Ok(false),
+                Err(e) => Err(e),
+            };
+
+            Ok(Identity {
+                crypto_ap: probe(&crypto::DF_NAME)?,
+                surface_ap: probe(&surface::DF_NAME)?,
+                support_ap: probe(&support::DF_NAME)?,
+            })
+        })
+    }
+
+    /// Runs the operation exclusively from other applications, in a transaction of the delegate.
+    /// Other threads wait for the operation, and nested ones are covered by the outermost one.
+    pub fn transaction<R, F>(&self, ctx: Ctx, f: F) -> Result<R, Error>
+    where
+        F: FnOnce() -> Result<R, Error>,
+    {
+        let guard = self.lock.acquire();
+        let transaction = match self.transaction {
+            Some(transaction) if !guard.is_nested() => transaction,
+            _ => return f(),
+        };
+
+        let mut f = Some(f);
+        let mut result = None;
+        transaction(&self.delegate, ctx, &mut || result = f.take().map(|f| f()))?;
+
+        result.unwrap_or_else(|| Err(Error::Device("The transaction was not run".to_string())))
+    }
+
+    /// Runs the operation in a transaction, selecting the DF first unless it is selected already.
+    /// APs sharing the card use this, as others may select their DF between the operations.
+    pub fn transaction_in<R, F>(&self, ctx: Ctx, df: &[u8], f: F) -> Result<R, Error>
+    where
+        F: FnOnce() -> Result<R, Error>,
+    {
+        self.transaction(ctx, || {
+            let selected = lock(&self.selected_df).as_deref() == Some(df);
+            if !selected {
+                self.select_df(ctx, df.into())?;
+            }
+
+            f()
+        })
+    }
+
     /// Extracts the size of current file by reading DER-encoded ASN.1 header.
     pub fn read_der_size(&self, ctx: Ctx) -> Result<u16, Error> {
         let header = self.read(ctx, Some(7))?;
@@ -150,10 +370,66 @@ where
         Ok(entire_size_from_partial(&header) as u16)
     }
 
+    /// Verifies the PIN in the DF, then remembers the session as verified.
+    pub(crate) fn begin_session(
+        &self,
+        ctx: Ctx,
+        df: &[u8],
+        ef: [u8; 2],
+        pin: &SecretPin,
+        id: u64,
+    ) -> Result<(), Error> {
+        self.transaction_in(ctx, df, || {
+            self.verify_pin(ctx, ef, pin)?;
+            lock(&self.sessions).push(id);
+
+            Ok(())
+        })
+    }
+
+    /// Runs the operation in the session, without selecting the DF not to reset the status.
+    pub(crate) fn transaction_in_session<R, F>(
+        &self,
+        ctx: Ctx,
+        df: &[u8],
+        id: u64,
+        f: F,
+    ) -> Result<R, Error>
+    where
+        F: FnOnce() -> Result<R, Error>,
+    {
+        self.transaction(ctx, || match self.is_in_session(df, id) {
+            true => f(),
+            _ => Err(Error::SessionEnded),
+        })
+    }
+
+    /// Ends the session, resetting the security status by selecting the DF again if still kept.
+    pub(crate) fn end_session(&self, ctx: Ctx, df: &[u8], id: u64) -> Result<(), Error> {
+        let _guard = self.lock.acquire();
+        if !self.is_in_session(df, id) {
+            return Ok(());
+        }
+
+        self.select_df(ctx, df.into())
+    }
+
+    /// Determines whether the session is verified, and the DF stays selected since then.
+    pub(crate) fn is_in_session(&self, df: &[u8], id: u64) -> bool {
+        let _guard = self.lock.acquire();
+
+        lock(&self.selected_df).as_deref() == Some(df) && lock(&self.sessions).contains(&id)
+    }
+
     fn handle<'a>(&'a self, ctx: Ctx, command: impl Into<Command<'a>>) -> Result<Vec<u8>, Error> {
+        let _guard = self.lock.acquire();
         let command = command.into();
         let mut len = command.le.unwrap_or_default() as usize;
-        let command_buf = Vec::from(command);
+        // Commands can contain the PIN, so they are wiped after sent.
+        let command_buf = Zeroizing::new(Vec::from(command));
+
+        #[cfg(feature = "tracing")]
+        pcap::capture(pcap::Direction::Command, &command_buf);
 
         let response = loop {
             let mut response = Vec::with_capacity(len);
@@ -163,16 +439,287 @@ where
                 response.set_len(len);
             }
 
+            let result = self
+                .delegate
+                .handle_in_ctx(ctx, &command_buf, &mut response);
+            self.check_reset();
+
+            let len = match result {
+                Ok(l) => l,
+                Err(HandleError::NotEnoughBuffer(l)) => {
+                    len = l;
+                    continue;
+                }
+                Err(HandleError::Nfc(e)) => return Err(Error::Device(e.to_string())),
+            };
+
+            response.truncate(len);
+
+            break response;
+        };
+
+        #[cfg(feature = "tracing")]
+        pcap::capture(pcap::Direction::Response, &response);
+
+        parse_response(&response)
+    }
+
+    /// Ends the sessions and forgets the DF selected, if the card has been reset.
+    fn check_reset(&self) {
+        if self
+            .take_reset
+            .is_some_and(|take_reset| take_reset(&self.delegate))
+        {
+            lock(&self.sessions).clear();
+            *lock(&self.selected_df) = None;
+        }
+    }
+}
+
+impl<T, Ctx> Card<T, Ctx>
+where
+    T: nfc::PinPad<Ctx>,
+    Ctx: Copy,
+{
+    /// Selects a EF then verifies the PIN entered on the PIN pad using the EF.
+    pub fn verify_pin_on_pad(
+        &self,
+        ctx: Ctx,
+        ef: [u8; 2],
+        format: &PinFormat,
+    ) -> Result<(), Error> {
+        let _guard = self.lock.acquire();
+        self.select_ef(ctx, ef.into())?;
+
+        let response =
+            self.delegate
+                .verify_pin_on_pad(ctx, format, [0x00, VERIFY_INS, 0x00, VERIFY_P2])?;
+
+        parse_response(&response).map(|_| ())
+    }
+
+    /// Selects a EF then changes the PIN using the EF, entering both current and new PINs on the
+    /// PIN pad.
+    pub fn change_pin_on_pad(
+        &self,
+        ctx: Ctx,
+        ef: [u8; 2],
+        format: &PinFormat,
+    ) -> Result<(), Error> {
+        self.transaction(ctx, || {
+            self.verify_pin_on_pad(ctx, ef, format)?;
+
+            let response = self.delegate.modify_pin_on_pad(
+                ctx,
+                format,
+                [0x00, CHANGE_INS, CHANGE_P1, CHANGE_P2],
+            )?;
+
+            parse_response(&response).map(|_| ())
+        })
+    }
+}
+
+/// An asynchronous counterpart of [`Card`], to communicate through an async delegate.
+/// Operations are run while locking the card, so they are never interleaved.
+#[cfg(feature = "async")]
+pub struct AsyncCard<T, Ctx>
+where
+    T: nfc::AsyncHandlerInCtx<Ctx>,
+    Ctx: Copy + Send + Sync + 'static,
+{
+    delegate: Box<T>,
+    selected_df: async_lock::Mutex<Option<Vec<u8>>>,
+    retry_guard: Option<RetryGuard>,
+    _ctx: PhantomData<Ctx>,
+}
+
+#[cfg(feature = "async")]
+impl<T, Ctx> AsyncCard<T, Ctx>
+where
+    T: nfc::AsyncHandlerInCtx<Ctx>,
+    Ctx: Copy + Send + Sync + 'static,
+{
+    /// Initiates an adapter with the delegate.
+    pub fn new(delegate: Box<T>) -> Self {
+        Self {
+            delegate,
+            selected_df: async_lock::Mutex::new(None),
+            retry_guard: None,
+            _ctx: PhantomData,
+        }
+    }
+
+    /// Guards the PINs from being blocked, that is opt-in.
+    pub fn with_retry_guard(mut self, guard: RetryGuard) -> Self {
+        self.retry_guard = Some(guard);
+        self
+    }
+
+    /// Locks the card to run operations, waiting for others to finish.
+    pub async fn lock(&self) -> AsyncCardGuard<'_, T, Ctx> {
+        AsyncCardGuard {
+            delegate: &self.delegate,
+            selected_df: self.selected_df.lock().await,
+            retry_guard: self.retry_guard.as_ref(),
+            _ctx: PhantomData,
+        }
+    }
+
+    /// Locks the card, then selects the DF unless it is selected already.
+    pub async fn lock_in(&self, ctx: Ctx, df: &[u8]) -> Result<AsyncCardGuard<'_, T, Ctx>, Error> {
+        let mut guard = self.lock().await;
+        if guard.selected_df.as_deref() != Some(df) {
+            guard.select_df(ctx, df.into()).await?;
+        }
+
+        Ok(guard)
+    }
+}
+
+/// The card locked to run operations, that is unlocked when dropped.
+#[cfg(feature = "async")]
+pub struct AsyncCardGuard<'c, T, Ctx>
+where
+    T: nfc::AsyncHandlerInCtx<Ctx>,
+    Ctx: Copy + Send + Sync + 'static,
+{
+    delegate: &'c T,
+    selected_df: async_lock::MutexGuard<'c, Option<Vec<u8>>>,
+    retry_guard: Option<&'c RetryGuard>,
+    _ctx: PhantomData<Ctx>,
+}
+
+#[cfg(feature = "async")]
+impl<'c, T, Ctx> AsyncCardGuard<'c, T, Ctx>
+where
+    T: nfc::AsyncHandlerInCtx<Ctx>,
+    Ctx: Copy + Send + Sync + 'static,
+{
+    /// Selects a DF with their name.
+    pub async fn select_df(&mut self, ctx: Ctx, name: Vec<u8>) -> Result<(), Error> {
+        let result = self
+            .handle(ctx, command::select_file(SELECT_P1_DF, SELECT_P2, &name))
+            .await
+            .map(|_| ());
+
+        *self.selected_df = result.as_ref().ok().map(|_| name);
+
+        result
+    }
+
+    /// Selects a EF with their name.
+    pub async fn select_ef(&mut self, ctx: Ctx, id: Vec<u8>) -> Result<(), Error> {
+        self.handle(ctx, command::select_file(SELECT_P1_EF, SELECT_P2, &id))
+            .await
+            .map(|_| ())
+    }
+
+    /// Reads binary from the selected file for `len` octets max.
+    pub async fn read(&mut self, ctx: Ctx, len: Option<u16>) -> Result<Vec<u8>, Error> {
+        let mut pos: u16 = 0;
+        let mut buf: Vec<u8> = Vec::new();
+
+        while match len {
+            Some(l) => pos < l,
+            None => true,
+        } {
+            let [p1, p2] = pos.to_be_bytes();
+            let le: u8 = match len {
+                Some(l) => match l - pos > 0xFF {
+                    true => 0,
+                    _ => (l & 0xFF) as u8,
+                },
+                _ => 0,
+            };
+
+            let mut fragment = self.handle(ctx, command::read_binary(p1, p2, le)).await?;
+            let length = fragment.len();
+
+            buf.append(&mut fragment);
+            pos += length as u16;
+
+            if (length as u8) < le {
+                break;
+            }
+        }
+
+        Ok(buf)
+    }
+
+    /// Verifies the PIN.
+    pub async fn verify(&mut self, ctx: Ctx, pin: &SecretPin) -> Result<(), Error> {
+        self.handle(ctx, command::verify(VERIFY_P2, pin.expose()))
+            .await
+            .map(|_| ())
+    }
+
+    /// Computes a signature using the selected key.
+    pub async fn sign(&mut self, ctx: Ctx, digest: Vec<u8>) -> Result<Vec<u8>, Error> {
+        self.handle(
+            ctx,
+            Command::new_with_payload_le(SIGN_CLA, SIGN_INS, SIGN_P1, SIGN_P2, 0, &digest),
+        )
+        .await
+    }
+
+    /// Selects a EF then verifies the pin using the EF.
+    pub async fn verify_pin(
+        &mut self,
+        ctx: Ctx,
+        ef: [u8; 2],
+        pin: &SecretPin,
+    ) -> Result<(), Error> {
+        self.select_ef(ctx, ef.into()).await?;
+
+        if let (Some(retry_guard), false) = (self.retry_guard, pin.is_empty()) {
+            retry_guard.check(self.verify(ctx, &SecretPin::default()).await)?;
+        }
+
+        self.verify(ctx, pin).await
+    }
+
+    pub async fn pin_status(&mut self, ctx: Ctx, ef: [u8; 2]) -> Result<u8, Error> {
+        match self.verify_pin(ctx, ef, &SecretPin::default()).await {
+            Ok(_) => Ok(0),
+            Err(Error::Apdu(nfc::Error::VerifyFailed(count))) => Ok(count),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Extracts the size of current file by reading DER-encoded ASN.1 header.
+    pub async fn read_der_size(&mut self, ctx: Ctx) -> Result<u16, Error> {
+        let header = self.read(ctx, Some(7)).await?;
+
+        Ok(entire_size_from_partial(&header) as u16)
+    }
+
+    async fn handle<'a>(
+        &self,
+        ctx: Ctx,
+        command: impl Into<Command<'a>>,
+    ) -> Result<Vec<u8>, Error> {
+        let command = command.into();
+        let mut len = command.le.unwrap_or_default() as usize;
+        // Commands can contain the PIN, so they are wiped after sent.
+        let command_buf = Zeroizing::new(Vec::from(command));
+
+        #[cfg(feature = "tracing")]
+        pcap::capture(pcap::Direction::Command, &command_buf);
+
+        let response = loop {
+            let mut response = vec![0; len];
             let len = match self
                 .delegate
                 .handle_in_ctx(ctx, &command_buf, &mut response)
+                .await
             {
                 Ok(l) => l,
                 Err(HandleError::NotEnoughBuffer(l)) => {
                     len = l;
                     continue;
                 }
-                Err(HandleError::Nfc(e)) => return Err(Error::Device(e)),
+                Err(e) => return Err(e.into()),
             };
 
             response.truncate(len);
@@ -180,8 +727,162 @@ where
             break response;
         };
 
-        Result::from(Response::from(response.as_slice()))
-            .map(|p| p.to_vec())
-            .map_err(|e| nfc::Error::from(e).into())
+        #[cfg(feature = "tracing")]
+        pcap::capture(pcap::Direction::Response, &response);
+
+        parse_response(&response)
+    }
+}
+
+fn parse_response(response: &[u8]) -> Result<Vec<u8>, Error> {
+    Result::from(Response::from(response))
+        .map(|p| p.to_vec())
+        .map_err(|e| nfc::Error::from(e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_guard() {
+        let remaining = |n| Err(Error::Apdu(nfc::Error::VerifyFailed(n)));
+
+        let guard = RetryGuard::new(1);
+        assert!(guard.check(Ok(())).is_ok());
+        assert!(guard.check(remaining(2)).is_ok());
+        assert!(matches!(
+            guard.check(remaining(1)),
+            Err(Error::FewRetriesLeft(1)),
+        ));
+
+        let guard = RetryGuard::new(1).with_confirmation(|remaining| remaining == 1);
+        assert!(guard.check(remaining(1)).is_ok());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async_card() {
+        let mut select_df = vec![0x00, 0xA4, 0x04, 0x0C, 0x03];
+        select_df.extend_from_slice(&[0x01, 0x02, 0x03]);
+
+        let card = AsyncCard::new(Box::new(Scripted::new(vec![
+            (select_df, vec![0x90, 0x00]),
+            (
+                vec![0x00, 0xA4, 0x02, 0x0C, 0x02, 0x00, 0x01],
+                vec![0x90, 0x00],
+            ),
+            (
+                vec![0x00, 0xB0, 0x00, 0x00, 0x02],
+                vec![0x12, 0x34, 0x90, 0x00],
+            ),
+        ])));
+
+        block_on(async {
+            drop(card.lock_in((), &[0x01, 0x02, 0x03]).await.unwrap());
+
+            // The DF is not selected again while it stays selected.
+            let mut guard = card.lock_in((), &[0x01, 0x02, 0x03]).await.unwrap();
+            guard.select_ef((), vec![0x00, 0x01]).await.unwrap();
+            assert_eq!(vec![0x12, 0x34], guard.read((), Some(2)).await.unwrap());
+        });
+
+        assert_eq!(0, card.delegate.remaining());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async_ap() {
+        use std::sync::Arc;
+
+        use crate::ap::{support, AsyncSupportAp};
+
+        let mut select_df = vec![0x00, 0xA4, 0x04, 0x0C, 0x0A];
+        select_df.extend_from_slice(&support::DF_NAME);
+
+        let card = Arc::new(AsyncCard::new(Box::new(Scripted::new(vec![
+            (select_df, vec![0x90, 0x00]),
+            (
+                vec![0x00, 0xA4, 0x02, 0x0C, 0x02, 0x00, 0x11],
+                vec![0x90, 0x00],
+            ),
+            (vec![0x00, 0x20, 0x00, 0x80, 0x00], vec![0x63, 0xC3]),
+        ]))));
+
+        let status = block_on(async {
+            let support_ap = AsyncSupportAp::open((), Arc::clone(&card)).await.unwrap();
+            support_ap.pin_status(()).await.unwrap()
+        });
+
+        assert_eq!(3, status);
+        assert_eq!(0, card.delegate.remaining());
+    }
+
+    /// Responds to the commands as scripted, in order.
+    #[cfg(feature = "async")]
+    struct Scripted(std::sync::Mutex<std::collections::VecDeque<(Vec<u8>, Vec<u8>)>>);
+
+    #[cfg(feature = "async")]
+    impl Scripted {
+        fn new(exchanges: Vec<(Vec<u8>, Vec<u8>)>) -> Self {
+            Self(std::sync::Mutex::new(exchanges.into()))
+        }
+
+        fn remaining(&self) -> usize {
+            self.0.lock().unwrap().len()
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[async_trait::async_trait]
+    impl nfc::AsyncHandlerInCtx for Scripted {
+        async fn handle_in_ctx(
+            &self,
+            _: (),
+            command: &[u8],
+            response: &mut [u8],
+        ) -> Result<usize, HandleError> {
+            let mut exchanges = self.0.lock().unwrap();
+            let (expected, scripted) = exchanges.front().expect("Unexpected command");
+            assert_eq!(expected, command);
+
+            let len = scripted.len();
+            if response.len() < len {
+                return Err(HandleError::NotEnoughBuffer(len));
+            }
+
+            response[..len].copy_from_slice(scripted);
+            exchanges.pop_front();
+
+            Ok(len)
+        }
+    }
+
+    /// Runs the future to completion on the current thread.
+    #[cfg(feature = "async")]
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        use std::pin::pin;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+        use std::thread::{self, Thread};
+
+        struct Unparker(Thread);
+
+        impl Wake for Unparker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(Unparker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
     }
 }
@@ -0,0 +1,128 @@
+//! Confirmation of what is being signed, before the PIN is sent.
+//!
+//! The input to be signed is opaque to the user, so hosts show the key-pair, the hash algorithm
+//! and the fingerprint of the digest, then ask the user whether to sign them.
+//! [`ConfirmPolicy`] decides the key-pairs to confirm, and can be attached to
+//! [`crate::ap::CryptoAp`] or called directly by hosts before asking the PIN.
+
+use crate::ap::crypto::Key;
+use crate::card;
+use crate::digest_info::{DigestPolicy, HashAlgorithm};
+
+/// What is being signed, to be shown to the user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Signing<'a> {
+    pub key: Key,
+
+    /// Hash algorithm of the digest, or `None` if the input is not DigestInfo.
+    pub algorithm: Option<HashAlgorithm>,
+
+    /// Digest of the document, or the input as is if it is not DigestInfo.
+    pub digest: &'a [u8],
+}
+
+impl<'a> Signing<'a> {
+    /// Describes the input to be signed using the key-pair.
+    pub fn new(key: Key, input: &'a [u8]) -> Self {
+        let (algorithm, digest) = match DigestPolicy::new(&HashAlgorithm::ALL).check(input) {
+            Ok(info) => (Some(info.algorithm), info.digest),
+            Err(_) => (None, input),
+        };
+
+        Self {
+            key,
+            algorithm,
+            digest,
+        }
+    }
+
+    /// Formats the digest in colon-separated uppercase hex, to be compared by the user.
+    pub fn fingerprint(&self) -> String {
+        self.digest
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+}
+
+/// Policy to confirm what is being signed with the user, before the PIN is sent.
+pub struct ConfirmPolicy {
+    keys: Vec<Key>,
+    confirm: Box<dyn Fn(&Signing<'_>) -> bool + Send + Sync>,
+}
+
+impl ConfirmPolicy {
+    /// Confirms signing using the key-pairs, such as `&[Key::Sign]` to confirm only for the
+    /// key-pair for signing.
+    /// Signing is refused without the confirmation set, so set one using
+    /// [`ConfirmPolicy::with_confirmation`].
+    pub fn new(keys: &[Key]) -> Self {
+        Self {
+            keys: keys.to_vec(),
+            confirm: Box::new(|_| false),
+        }
+    }
+
+    /// Sets the confirmation, that shows what is being signed and returns whether to sign them.
+    pub fn with_confirmation<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Signing<'_>) -> bool + Send + Sync + 'static,
+    {
+        self.confirm = Box::new(f);
+        self
+    }
+
+    /// Confirms signing the input using the key-pair, if required by the policy.
+    pub fn confirm(&self, key: Key, input: &[u8]) -> Result<(), card::Error> {
+        if !self.keys.contains(&key) || (self.confirm)(&Signing::new(key, input)) {
+            return Ok(());
+        }
+
+        Err(card::Error::NotConfirmed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_confirm() {
+        let mut input = vec![
+            0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x01, 0x05, 0x00, 0x04, 0x20,
+        ];
+        input.extend_from_slice(&[0xAB; 32]);
+
+        let asked = Arc::new(AtomicUsize::new(0));
+        let policy = ConfirmPolicy::new(&[Key::Sign]).with_confirmation({
+            let asked = Arc::clone(&asked);
+            move |signing| {
+                asked.fetch_add(1, Ordering::SeqCst);
+                assert_eq!(Key::Sign, signing.key);
+                assert_eq!(Some(HashAlgorithm::Sha256), signing.algorithm);
+                assert!(signing.fingerprint().starts_with("AB:AB:"));
+
+                false
+            }
+        });
+
+        assert!(policy.confirm(Key::Auth, &input).is_ok());
+        assert!(matches!(
+            policy.confirm(Key::Sign, &input),
+            Err(card::Error::NotConfirmed),
+        ));
+        assert_eq!(1, asked.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_signing_raw() {
+        let signing = Signing::new(Key::Auth, &[0x01, 0x23]);
+        assert_eq!(None, signing.algorithm);
+        assert_eq!("01:23", signing.fingerprint());
+    }
+}
@@ -93,3 +93,29 @@ pub fn entire_size_from_partial(header: &[u8]) -> usize {
 
     reader.read_length() + reader.cursor
 }
+
+/// Reads the TLV of the tag in the definite length, returning the value and the rest.
+/// Unlike [`Reader`], fails instead of panicking on malformed input, to parse untrusted data.
+pub(crate) fn read_tlv(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (&actual, input) = input.split_first()?;
+    if actual != tag {
+        return None;
+    }
+
+    let (&head, input) = input.split_first()?;
+    let (len, input) = match head {
+        0x00..=0x7F => (head as usize, input),
+        0x81..=0x84 => {
+            let octets = (head & 0x7F) as usize;
+            if input.len() < octets {
+                return None;
+            }
+
+            let (len, input) = input.split_at(octets);
+            (len.iter().fold(0, |acc, &b| (acc << 8) | b as usize), input)
+        }
+        _ => return None,
+    };
+
+    (input.len() >= len).then(|| input.split_at(len))
+}
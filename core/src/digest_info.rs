@@ -0,0 +1,238 @@
+//! Strict parsing of DigestInfo, the input to be signed by the card in PKCS #1 v1.5.
+//!
+//! The card signs any bytes given, so callers can misuse them as a raw signing oracle.
+//! [`DigestPolicy`] accepts only DigestInfo of the allowed hash algorithms with the digest of
+//! the matching length, to be checked before the PIN is sent.
+
+use std::fmt::{Display, Formatter};
+
+use crate::der::read_tlv;
+
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_OID: u8 = 0x06;
+const TAG_NULL: u8 = 0x05;
+const TAG_OCTET_STRING: u8 = 0x04;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The input is not a DER-encoded DigestInfo")]
+    Malformed,
+
+    #[error("The hash algorithm is not allowed: {0}")]
+    Disallowed(String),
+
+    #[error("The digest of {algorithm} must be {expected} octets, but {actual} octets given")]
+    LengthMismatch {
+        algorithm: HashAlgorithm,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+/// Hash algorithms, that can be allowed in the policy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// SHA-1, that is only for legacy use.
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    pub(crate) const ALL: [Self; 4] = [Self::Sha1, Self::Sha256, Self::Sha384, Self::Sha512];
+
+    /// Gets the OID in DER-encoded form, without the tag and the length.
+    pub fn oid(self) -> &'static [u8] {
+        match self {
+            Self::Sha1 => &[0x2B, 0x0E, 0x03, 0x02, 0x1A],
+            Self::Sha256 => &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01],
+            Self::Sha384 => &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02],
+            Self::Sha512 => &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03],
+        }
+    }
+
+    /// Gets the length of the digest in octets.
+    pub fn digest_len(self) -> usize {
+        match self {
+            Self::Sha1 => 20,
+            Self::Sha256 => 32,
+            Self::Sha384 => 48,
+            Self::Sha512 => 64,
+        }
+    }
+}
+
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Sha1 => "SHA-1",
+            Self::Sha256 => "SHA-256",
+            Self::Sha384 => "SHA-384",
+            Self::Sha512 => "SHA-512",
+        })
+    }
+}
+
+/// DigestInfo parsed from the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DigestInfo<'a> {
+    pub algorithm: HashAlgorithm,
+    pub digest: &'a [u8],
+}
+
+/// Policy of the input to be signed, that allows only DigestInfo of the hash algorithms.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DigestPolicy {
+    allowed: Vec<HashAlgorithm>,
+}
+
+impl DigestPolicy {
+    /// Allows the hash algorithms only.
+    pub fn new(allowed: &[HashAlgorithm]) -> Self {
+        Self {
+            allowed: allowed.to_vec(),
+        }
+    }
+
+    /// Parses the input as DigestInfo, then checks the algorithm and the length of the digest.
+    pub fn check<'a>(&self, input: &'a [u8]) -> Result<DigestInfo<'a>, Error> {
+        let (oid, digest) = parse(input).ok_or(Error::Malformed)?;
+        let algorithm = HashAlgorithm::ALL
+            .into_iter()
+            .find(|a| a.oid() == oid && self.allowed.contains(a))
+            .ok_or_else(|| Error::Disallowed(format_oid(oid)))?;
+
+        if digest.len() != algorithm.digest_len() {
+            return Err(Error::LengthMismatch {
+                algorithm,
+                expected: algorithm.digest_len(),
+                actual: digest.len(),
+            });
+        }
+
+        Ok(DigestInfo { algorithm, digest })
+    }
+}
+
+impl Default for DigestPolicy {
+    /// Allows SHA-2 family only, as SHA-1 is no longer collision resistant.
+    fn default() -> Self {
+        Self::new(&[
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha384,
+            HashAlgorithm::Sha512,
+        ])
+    }
+}
+
+/// Parses DigestInfo into the OID and the digest, rejecting any trailing data.
+fn parse(input: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (info, rest) = read_tlv(input, TAG_SEQUENCE)?;
+    if !rest.is_empty() {
+        return None;
+    }
+
+    let (algorithm, info) = read_tlv(info, TAG_SEQUENCE)?;
+    let (oid, parameters) = read_tlv(algorithm, TAG_OID)?;
+    match read_tlv(parameters, TAG_NULL) {
+        // Parameters must be NULL if present.
+        Some((null, rest)) if null.is_empty() && rest.is_empty() => (),
+        _ if parameters.is_empty() => (),
+        _ => return None,
+    }
+
+    let (digest, rest) = read_tlv(info, TAG_OCTET_STRING)?;
+
+    rest.is_empty().then_some((oid, digest))
+}
+
+/// Formats the OID in the dotted notation, to be shown in errors.
+fn format_oid(oid: &[u8]) -> String {
+    let mut arcs = vec![];
+    let mut arc = 0u64;
+    for &b in oid {
+        arc = (arc << 7) | (b & 0x7F) as u64;
+        if b & 0x80 == 0 {
+            arcs.push(arc);
+            arc = 0;
+        }
+    }
+
+    let mut parts = match arcs.first() {
+        Some(&first) => vec![(first / 40).min(2), first - (first / 40).min(2) * 40],
+        None => return String::new(),
+    };
+    parts.extend_from_slice(&arcs[1..]);
+
+    parts
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256_PREFIX: [u8; 19] = [
+        0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01,
+        0x05, 0x00, 0x04, 0x20,
+    ];
+
+    #[test]
+    fn test_check() {
+        let mut input = SHA256_PREFIX.to_vec();
+        input.extend_from_slice(&[0xAB; 32]);
+
+        let info = DigestPolicy::default().check(&input).unwrap();
+        assert_eq!(HashAlgorithm::Sha256, info.algorithm);
+        assert_eq!(&[0xAB; 32], info.digest);
+
+        input.push(0x00);
+        assert!(matches!(
+            DigestPolicy::default().check(&input),
+            Err(Error::Malformed),
+        ));
+
+        assert!(matches!(
+            DigestPolicy::default().check(b"arbitrary data to be signed"),
+            Err(Error::Malformed),
+        ));
+    }
+
+    #[test]
+    fn test_check_disallowed() {
+        let mut input = vec![
+            0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2B, 0x0E, 0x03, 0x02, 0x1A, 0x05, 0x00, 0x04,
+            0x14,
+        ];
+        input.extend_from_slice(&[0xAB; 20]);
+
+        match DigestPolicy::default().check(&input) {
+            Err(Error::Disallowed(oid)) => assert_eq!("1.3.14.3.2.26", oid),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        let policy = DigestPolicy::new(&[HashAlgorithm::Sha1]);
+        assert!(policy.check(&input).is_ok());
+    }
+
+    #[test]
+    fn test_check_length_mismatch() {
+        let mut input = SHA256_PREFIX.to_vec();
+        input[1] = 0x2D;
+        input[18] = 0x1C;
+        input.extend_from_slice(&[0xAB; 28]);
+
+        assert!(matches!(
+            DigestPolicy::default().check(&input),
+            Err(Error::LengthMismatch {
+                expected: 32,
+                actual: 28,
+                ..
+            }),
+        ));
+    }
+}
@@ -6,8 +6,23 @@ extern crate core;
 pub mod pcsc;
 
 pub mod ap;
+pub mod atr;
+#[cfg(feature = "audit")]
+pub mod audit;
 pub mod card;
+pub mod confirm;
 pub mod der;
+pub mod digest_info;
 pub mod nfc;
+pub mod pcap;
+pub mod pinpad;
+pub mod redact;
+pub mod secret;
+pub mod session;
+pub mod transcript;
 
+mod sync;
+
+#[cfg(feature = "async")]
+pub use card::AsyncCard;
 pub use card::Card;
@@ -3,3 +3,60 @@
 pub use apdu::core::{HandleError, HandlerInCtx, Result};
 pub use apdu::Error;
 pub use apdu::{Command, Response};
+
+use crate::pinpad::PinFormat;
+
+/// A handler that can run multiple commands exclusively from other applications,
+/// such as transactions in PC/SC.
+/// Enable them on the card by [`crate::Card::with_transactions`]; handlers without such a concept
+/// do not need to implement this.
+pub trait Transactional<Ctx = ()>: HandlerInCtx<Ctx> {
+    /// Runs the function in a transaction, that is begun before and ended after the call.
+    fn transaction<R, F>(&self, _ctx: Ctx, f: F) -> std::result::Result<R, HandleError>
+    where
+        F: FnOnce() -> R,
+    {
+        Ok(f())
+    }
+
+    /// Determines whether the card has been reset since the last call, such as by reconnecting.
+    /// The security status and the DF selected are lost on the card by resets.
+    fn take_reset(&self) -> bool {
+        false
+    }
+}
+
+/// A handler with a PIN pad, that sends commands with the PIN entered on the reader.
+/// The PIN never passes the host, so they are safe from malwares on them.
+pub trait PinPad<Ctx = ()>: HandlerInCtx<Ctx> {
+    /// Sends the command with the PIN entered, appended to the header.
+    /// Returns the response from the card.
+    fn verify_pin_on_pad(
+        &self,
+        ctx: Ctx,
+        format: &PinFormat,
+        header: [u8; 4],
+    ) -> std::result::Result<Vec<u8>, HandleError>;
+
+    /// Sends the command with the new PIN entered twice, appended to the header.
+    /// Returns the response from the card.
+    fn modify_pin_on_pad(
+        &self,
+        ctx: Ctx,
+        format: &PinFormat,
+        header: [u8; 4],
+    ) -> std::result::Result<Vec<u8>, HandleError>;
+}
+
+/// An asynchronous counterpart of [`HandlerInCtx`], for transports that are naturally async such as
+/// relays over the network.
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+pub trait AsyncHandlerInCtx<Ctx = ()>: Send + Sync
+where
+    Ctx: Send + 'static,
+{
+    /// Sends the command, then writes the response into the buffer.
+    /// Returns the length of the response written.
+    async fn handle_in_ctx(&self, ctx: Ctx, command: &[u8], response: &mut [u8]) -> Result;
+}
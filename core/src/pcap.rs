@@ -0,0 +1,345 @@
+//! Capturing APDUs into pcapng files, to be analysed on Wireshark.
+//!
+//! APDUs are written as packets of the ISO 7816 link type (`LINKTYPE_ISO_7816`), with the
+//! direction in the flags: commands are outbound and responses are inbound.
+//! Wireshark decodes them as ISO 7816 APDUs as is, without any preferences.
+//!
+//! Wrap any handler in [`Capture`] to capture them. With the `tracing` feature, every command
+//! through [`crate::Card`] is also captured into the sink installed by `install`.
+//! PINs and personal data are redacted as in [`crate::redact`], unless the unsafe full trace is
+//! enabled.
+
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::nfc::{HandleError, HandlerInCtx, PinPad, Transactional};
+use crate::pinpad::PinFormat;
+use crate::redact::Redactor;
+use crate::sync::lock;
+
+/// Link type of the packets, registered for ISO 7816 APDUs.
+pub const LINKTYPE_ISO_7816: u16 = 266;
+
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x00000001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x00000006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B3C4D;
+const OPTION_END: u16 = 0;
+const OPTION_EPB_FLAGS: u16 = 2;
+
+#[cfg(feature = "tracing")]
+static SINK: Mutex<Option<Sink>> = Mutex::new(None);
+
+#[cfg(feature = "tracing")]
+struct Sink {
+    writer: PcapWriter<Box<dyn Write + Send>>,
+    redactor: Redactor,
+}
+
+/// Direction of the APDU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Command from the host to the card.
+    Command,
+
+    /// Response from the card to the host.
+    Response,
+}
+
+impl Direction {
+    /// Encodes the direction in `epb_flags`, where commands are outbound.
+    fn flags(self) -> u32 {
+        match self {
+            Self::Command => 0b10,
+            Self::Response => 0b01,
+        }
+    }
+}
+
+/// A writer of APDUs in pcapng format.
+pub struct PcapWriter<W>
+where
+    W: Write,
+{
+    writer: W,
+}
+
+impl<W> PcapWriter<W>
+where
+    W: Write,
+{
+    /// Writes the headers of pcapng into the writer.
+    pub fn new(mut writer: W) -> std::io::Result<Self> {
+        let mut shb = vec![];
+        shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend_from_slice(&1u16.to_le_bytes()); // Major version
+        shb.extend_from_slice(&0u16.to_le_bytes()); // Minor version
+        shb.extend_from_slice(&(-1i64).to_le_bytes()); // Section length: unspecified
+        write_block(&mut writer, BLOCK_SECTION_HEADER, &shb)?;
+
+        let mut idb = vec![];
+        idb.extend_from_slice(&LINKTYPE_ISO_7816.to_le_bytes());
+        idb.extend_from_slice(&0u16.to_le_bytes()); // Reserved
+        idb.extend_from_slice(&0u32.to_le_bytes()); // Snap length: unlimited
+        write_block(&mut writer, BLOCK_INTERFACE_DESCRIPTION, &idb)?;
+
+        writer.flush()?;
+
+        Ok(Self { writer })
+    }
+
+    /// Writes the APDU as a packet, timestamped now.
+    pub fn write_apdu(&mut self, direction: Direction, apdu: &[u8]) -> std::io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        self.write_apdu_at(direction, apdu, timestamp.as_micros() as u64)
+    }
+
+    /// Writes the APDU as a packet, timestamped in microseconds since the UNIX epoch.
+    pub fn write_apdu_at(
+        &mut self,
+        direction: Direction,
+        apdu: &[u8],
+        timestamp: u64,
+    ) -> std::io::Result<()> {
+        let mut epb = vec![];
+        epb.extend_from_slice(&0u32.to_le_bytes()); // Interface ID
+        epb.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(timestamp as u32).to_le_bytes());
+        epb.extend_from_slice(&(apdu.len() as u32).to_le_bytes()); // Captured length
+        epb.extend_from_slice(&(apdu.len() as u32).to_le_bytes()); // Original length
+        epb.extend_from_slice(apdu);
+        pad(&mut epb);
+
+        epb.extend_from_slice(&OPTION_EPB_FLAGS.to_le_bytes());
+        epb.extend_from_slice(&4u16.to_le_bytes());
+        epb.extend_from_slice(&direction.flags().to_le_bytes());
+        epb.extend_from_slice(&OPTION_END.to_le_bytes());
+        epb.extend_from_slice(&0u16.to_le_bytes());
+
+        write_block(&mut self.writer, BLOCK_ENHANCED_PACKET, &epb)?;
+        self.writer.flush()
+    }
+
+    /// Gets the writer back.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// A handler that captures every command and response through the delegate.
+pub struct Capture<T, W>
+where
+    W: Write,
+{
+    delegate: T,
+    writer: Mutex<(PcapWriter<W>, Redactor)>,
+}
+
+impl<T, W> Capture<T, W>
+where
+    W: Write,
+{
+    /// Captures the APDUs into the writer.
+    pub fn new(delegate: T, writer: W) -> std::io::Result<Self> {
+        Ok(Self {
+            delegate,
+            writer: Mutex::new((PcapWriter::new(writer)?, Redactor::default())),
+        })
+    }
+
+    /// Gets the delegate and the writer back.
+    pub fn into_inner(self) -> (T, W) {
+        let (writer, _) = self.writer.into_inner().unwrap_or_else(|e| e.into_inner());
+
+        (self.delegate, writer.into_inner())
+    }
+}
+
+impl<T, W, Ctx> HandlerInCtx<Ctx> for Capture<T, W>
+where
+    T: HandlerInCtx<Ctx>,
+    W: Write,
+{
+    fn handle_in_ctx(
+        &self,
+        ctx: Ctx,
+        command: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, HandleError> {
+        let to_error = |e: std::io::Error| HandleError::Nfc(Box::new(e));
+
+        {
+            let (writer, redactor) = &mut *lock(&self.writer);
+            let command = redactor.command(command);
+            writer
+                .write_apdu(Direction::Command, &command.apdu)
+                .map_err(to_error)?;
+        }
+
+        let len = self.delegate.handle_in_ctx(ctx, command, response)?;
+
+        let (writer, redactor) = &mut *lock(&self.writer);
+        let response = redactor.response(&response[..len]);
+        writer
+            .write_apdu(Direction::Response, &response.apdu)
+            .map_err(to_error)?;
+
+        Ok(len)
+    }
+}
+
+impl<T, W, Ctx> Transactional<Ctx> for Capture<T, W>
+where
+    T: Transactional<Ctx>,
+    W: Write,
+{
+    fn transaction<R, F>(&self, ctx: Ctx, f: F) -> Result<R, HandleError>
+    where
+        F: FnOnce() -> R,
+    {
+        self.delegate.transaction(ctx, f)
+    }
+
+    fn take_reset(&self) -> bool {
+        self.delegate.take_reset()
+    }
+}
+
+impl<T, W, Ctx> PinPad<Ctx> for Capture<T, W>
+where
+    T: PinPad<Ctx>,
+    W: Write,
+{
+    fn verify_pin_on_pad(
+        &self,
+        ctx: Ctx,
+        format: &PinFormat,
+        header: [u8; 4],
+    ) -> Result<Vec<u8>, HandleError> {
+        let response = self.delegate.verify_pin_on_pad(ctx, format, header)?;
+        self.capture_on_pad(header, &response)?;
+
+        Ok(response)
+    }
+
+    fn modify_pin_on_pad(
+        &self,
+        ctx: Ctx,
+        format: &PinFormat,
+        header: [u8; 4],
+    ) -> Result<Vec<u8>, HandleError> {
+        let response = self.delegate.modify_pin_on_pad(ctx, format, header)?;
+        self.capture_on_pad(header, &response)?;
+
+        Ok(response)
+    }
+}
+
+impl<T, W> Capture<T, W>
+where
+    W: Write,
+{
+    /// Captures the command sent by the PIN pad as the header only, as the PIN never passes here.
+    fn capture_on_pad(&self, header: [u8; 4], response: &[u8]) -> Result<(), HandleError> {
+        let (writer, _) = &mut *lock(&self.writer);
+
+        writer
+            .write_apdu(Direction::Command, &header)
+            .and_then(|_| writer.write_apdu(Direction::Response, response))
+            .map_err(|e| HandleError::Nfc(Box::new(e)))
+    }
+}
+
+/// Installs the sink to capture every APDU through [`crate::Card`] into, replacing the old one.
+#[cfg(feature = "tracing")]
+pub fn install<W>(writer: W) -> std::io::Result<()>
+where
+    W: Write + Send + 'static,
+{
+    let writer: Box<dyn Write + Send> = Box::new(writer);
+    *lock(&SINK) = Some(Sink {
+        writer: PcapWriter::new(writer)?,
+        redactor: Redactor::default(),
+    });
+
+    Ok(())
+}
+
+/// Uninstalls the sink, flushing them.
+#[cfg(feature = "tracing")]
+pub fn uninstall() -> std::io::Result<()> {
+    match lock(&SINK).take() {
+        Some(sink) => sink.writer.into_inner().flush(),
+        None => Ok(()),
+    }
+}
+
+/// Captures the APDU into the installed sink, if any.
+/// Failures are ignored, not to interrupt the communication.
+#[cfg(feature = "tracing")]
+pub(crate) fn capture(direction: Direction, apdu: &[u8]) {
+    if let Some(sink) = lock(&SINK).as_mut() {
+        let redacted = match direction {
+            Direction::Command => sink.redactor.command(apdu),
+            Direction::Response => sink.redactor.response(apdu),
+        };
+
+        if let Err(e) = sink.writer.write_apdu(direction, &redacted.apdu) {
+            tracing::warn!("Failed to capture the APDU: {}", e);
+        }
+    }
+}
+
+/// Writes the block with the type and the body, followed by the total length.
+fn write_block<W: Write>(writer: &mut W, ty: u32, body: &[u8]) -> std::io::Result<()> {
+    let len = (body.len() + 12) as u32;
+
+    writer.write_all(&ty.to_le_bytes())?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&len.to_le_bytes())
+}
+
+/// Pads the buffer to 32 bits.
+fn pad(buf: &mut Vec<u8>) {
+    buf.resize((buf.len() + 3) & !3, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_apdu() {
+        let mut writer = PcapWriter::new(vec![]).unwrap();
+        let header_len = writer.writer.len();
+        assert_eq!(28 + 20, header_len);
+
+        writer
+            .write_apdu_at(Direction::Command, &[0x00, 0xA4, 0x04], 0x1_0000_0002)
+            .unwrap();
+
+        let buf = writer.into_inner();
+        assert_eq!(
+            vec![
+                0x06, 0x00, 0x00, 0x00, // Block type
+                0x30, 0x00, 0x00, 0x00, // Block length
+                0x00, 0x00, 0x00, 0x00, // Interface ID
+                0x01, 0x00, 0x00, 0x00, // Timestamp (high)
+                0x02, 0x00, 0x00, 0x00, // Timestamp (low)
+                0x03, 0x00, 0x00, 0x00, // Captured length
+                0x03, 0x00, 0x00, 0x00, // Original length
+                0x00, 0xA4, 0x04, 0x00, // Packet data with padding
+                0x02, 0x00, 0x04, 0x00, // epb_flags
+                0x02, 0x00, 0x00, 0x00, // Outbound
+                0x00, 0x00, 0x00, 0x00, // opt_endofopt
+                0x30, 0x00, 0x00, 0x00, // Block length
+            ],
+            buf[header_len..],
+        );
+    }
+}
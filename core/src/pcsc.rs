@@ -23,25 +23,73 @@
 //!
 //! let ctx = Context::try_new().unwrap();
 //! let device = ctx.open().unwrap();
-//! let pcsc_card = device.connect(ctx).unwrap();
+//! let pcsc_card = device.connect(&ctx).unwrap();
 //!
 //! let card = Rc::new(Card::new(Box::new(pcsc_card)));
 //! let jpki_ap = CryptoAp::open((), Rc::clone(&card)).unwrap();
 //! ```
+//!
+//! The card and the APs opened by `open_shared` can be shared across threads, as the operations
+//! are serialized.
+//! Enable transactions to keep other processes from interleaving the composite operations:
+//! ```rust,no_run
+//! use std::sync::Arc;
+//! use std::thread;
+//!
+//! use jpki::Card;
+//! use jpki::ap::{CryptoAp, SupportAp};
+//! use jpki::ap::crypto::CertType;
+//! use jpki::pcsc::Context;
+//! use jpki::secret::SecretPin;
+//!
+//! let ctx = Context::try_new().unwrap();
+//! let pcsc_card = ctx.open().unwrap().connect(&ctx).unwrap();
+//!
+//! let card = Arc::new(Card::new(Box::new(pcsc_card)).with_transactions());
+//! let crypto_ap = Arc::new(CryptoAp::open_shared((), Arc::clone(&card)).unwrap());
+//! let support_ap = SupportAp::open_shared((), Arc::clone(&card)).unwrap();
+//!
+//! let worker = {
+//!     let crypto_ap = Arc::clone(&crypto_ap);
+//!     thread::spawn(move || crypto_ap.read_certificate((), CertType::Auth, SecretPin::default()))
+//! };
+//!
+//! let status = support_ap.pin_status(()).unwrap();
+//! let certificate = worker.join().unwrap().unwrap();
+//! ```
+//!
+//! A context can connect to multiple cards at once, on the different readers:
+//! ```rust,no_run
+//! use jpki::pcsc::Context;
+//!
+//! let ctx = Context::try_new().unwrap();
+//! let witness = ctx.open_by_name("Reader A").unwrap().connect(&ctx).unwrap();
+//! let applicant = ctx.open_by_name("Reader B").unwrap().connect(&ctx).unwrap();
+//! ```
 
+use std::collections::VecDeque;
 use std::ffi::{CStr, CString};
 use std::io::Write;
-use std::marker::PhantomData;
-use std::thread::sleep;
-use std::time::Duration;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 
 use apdu::core::HandleError;
-use pcsc::{Card, Protocols, Scope, ShareMode, MAX_BUFFER_SIZE};
+use pcsc::{Card, Protocols, ReaderState, Scope, State, MAX_BUFFER_SIZE};
+
+pub use pcsc::{Disposition, ShareMode};
 
 #[cfg(feature = "tracing")]
 use tracing::{debug, info};
 
-use crate::nfc::HandlerInCtx;
+use crate::atr::{self, Atr};
+use crate::nfc::{HandlerInCtx, PinPad, Transactional};
+use crate::pinpad::{self, Features, PinFormat};
+#[cfg(feature = "tracing")]
+use crate::redact::Redactor;
+use crate::sync::lock;
 
 #[cfg(not(feature = "tracing"))]
 macro_rules! debug {
@@ -60,27 +108,38 @@ pub enum Error {
 
     #[error("Reader not found on PC/SC service")]
     ReaderNotFound,
+
+    #[error("Timed out while waiting for a card")]
+    Timeout,
+
+    #[error("Waiting for a card was cancelled")]
+    Cancelled,
+
+    #[error("The reader does not support the feature of PIN pad")]
+    PinPadNotSupported,
+
+    #[error("Invalid ATR returned by the card: {0}")]
+    Atr(#[from] atr::Error),
 }
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
-/// PC/SC context.
-pub struct Context<'a> {
+/// PC/SC context, that can be shared by multiple devices and cards.
+#[derive(Clone)]
+pub struct Context {
     ctx: pcsc::Context,
-    _lifetime: PhantomData<&'a ()>,
 }
 
-impl<'a> Context<'a> {
+impl Context {
     /// Creates a PC/SC context in user scope.
     pub fn try_new() -> Result<Self> {
         Ok(Self {
             ctx: pcsc::Context::establish(Scope::User).map_err(Error::PcscError)?,
-            _lifetime: Default::default(),
         })
     }
 
     /// Finds a PC/SC device, then opens a connection to them.
-    pub fn open<'b>(&self) -> Result<Device<'b>> {
+    pub fn open(&self) -> Result<Device> {
         let mut buf = [0u8; 2048];
 
         Ok(Device::new(
@@ -91,91 +150,712 @@ impl<'a> Context<'a> {
                 .ok_or(Error::ReaderNotFound)?,
         ))
     }
+
+    /// Gets a handle to cancel waiting for a card on this context, from another thread.
+    pub fn canceller(&self) -> Canceller {
+        Canceller {
+            ctx: self.ctx.clone(),
+        }
+    }
+
+    /// Subscribes events on all readers, such as insertion or removal of cards.
+    /// Readers already connected and cards already inserted are notified at first.
+    ///
+    /// ```rust,no_run
+    /// use jpki::pcsc::{Context, Event};
+    ///
+    /// let ctx = Context::try_new().unwrap();
+    /// for event in ctx.events().unwrap() {
+    ///     match event.unwrap() {
+    ///         Event::CardInserted { reader, .. } => println!("Welcome! ({})", reader),
+    ///         Event::CardRemoved { .. } => println!("Good bye!"),
+    ///         _ => (),
+    ///     }
+    /// }
+    /// ```
+    pub fn events(&self) -> Result<Events> {
+        Events::new(self.ctx.clone())
+    }
+
+    /// Lists the readers connected to the PC/SC service, with whether a card is present on them.
+    pub fn readers(&self) -> Result<Vec<Reader>> {
+        let names = match self.ctx.list_readers_owned() {
+            Ok(names) => names,
+            Err(pcsc::Error::NoReadersAvailable) => return Ok(vec![]),
+            Err(e) => return Err(Error::PcscError(e)),
+        };
+
+        let mut states = names
+            .into_iter()
+            .map(|name| ReaderState::new(name, State::UNAWARE))
+            .collect::<Vec<_>>();
+
+        if !states.is_empty() {
+            // Returns immediately, as the current states are unaware.
+            self.ctx
+                .get_status_change(Duration::ZERO, &mut states)
+                .map_err(Error::PcscError)?;
+        }
+
+        Ok(states.iter().map(Reader::from).collect())
+    }
+
+    /// Opens a connection to the reader that has exactly the name.
+    pub fn open_by_name(&self, name: &str) -> Result<Device> {
+        self.open_matching(|reader| reader.name == name)
+    }
+
+    /// Opens a connection to the first reader that matches the predicate.
+    ///
+    /// ```rust,no_run
+    /// use jpki::pcsc::Context;
+    ///
+    /// let ctx = Context::try_new().unwrap();
+    /// let device = ctx.open_matching(|r| r.name.contains("PaSoRi")).unwrap();
+    /// let device = ctx.open_matching(|r| r.is_contactless()).unwrap();
+    /// ```
+    pub fn open_matching<P>(&self, predicate: P) -> Result<Device>
+    where
+        P: Fn(&Reader) -> bool,
+    {
+        Ok(Device::new(
+            &self
+                .readers()?
+                .into_iter()
+                .find(|reader| predicate(reader))
+                .ok_or(Error::ReaderNotFound)?
+                .raw,
+        ))
+    }
+}
+
+/// A handle to cancel waiting for a card, that can be sent to another thread.
+#[derive(Clone)]
+pub struct Canceller {
+    ctx: pcsc::Context,
+}
+
+impl Canceller {
+    /// Cancels the ongoing wait, then [`Device::connect`] returns [`Error::Cancelled`].
+    pub fn cancel(&self) -> Result<()> {
+        self.ctx.cancel().map_err(Error::PcscError)
+    }
+}
+
+/// An event on readers or cards, notified by the PC/SC service.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A reader is connected to the PC/SC service.
+    ReaderAttached { reader: String },
+
+    /// A reader is disconnected from the PC/SC service.
+    ReaderDetached { reader: String },
+
+    /// A card is inserted into, or placed on the reader.
+    CardInserted { reader: String, atr: Vec<u8> },
+
+    /// A card is removed from the reader.
+    CardRemoved { reader: String },
+}
+
+/// An iterator of events on all readers, that blocks until the next event occurs.
+/// The iteration ends when cancelled by the [`Canceller`] of the context,
+/// or no readers are left to watch.
+pub struct Events {
+    ctx: pcsc::Context,
+    states: Vec<ReaderState>,
+    pending: VecDeque<Event>,
+}
+
+impl Events {
+    fn new(ctx: pcsc::Context) -> Result<Self> {
+        // Receives notifications on attaching or detaching readers, using the special reader name.
+        let mut events = Self {
+            ctx,
+            states: vec![ReaderState::new(pcsc::PNP_NOTIFICATION(), State::UNAWARE)],
+            pending: VecDeque::new(),
+        };
+
+        events.refresh()?;
+
+        Ok(events)
+    }
+
+    /// Synchronises the list of readers to watch, with the readers actually connected.
+    fn refresh(&mut self) -> Result<()> {
+        let names = match self.ctx.list_readers_owned() {
+            Ok(names) => names,
+            Err(pcsc::Error::NoReadersAvailable) => vec![],
+            Err(e) => return Err(Error::PcscError(e)),
+        };
+
+        let mut detached = vec![];
+        self.states.retain(|state| {
+            let retain = is_pnp(state) || names.iter().any(|n| n.as_c_str() == state.name());
+            if !retain {
+                detached.push((reader_name(state), state.current_state()));
+            }
+
+            retain
+        });
+
+        for (reader, state) in detached {
+            self.detach(reader, state);
+        }
+
+        for name in names {
+            if self
+                .states
+                .iter()
+                .all(|state| state.name() != name.as_c_str())
+            {
+                debug!("Reader attached: {}", name.to_string_lossy());
+
+                self.pending.push_back(Event::ReaderAttached {
+                    reader: name.to_string_lossy().to_string(),
+                });
+                self.states.push(ReaderState::new(name, State::UNAWARE));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn detach(&mut self, reader: String, state: State) {
+        debug!("Reader detached: {}", reader);
+
+        if state.contains(State::PRESENT) {
+            self.pending.push_back(Event::CardRemoved {
+                reader: reader.clone(),
+            });
+        }
+
+        self.pending.push_back(Event::ReaderDetached { reader });
+    }
+
+    fn poll(&mut self) -> Result<()> {
+        match self.ctx.get_status_change(None, &mut self.states) {
+            Ok(_) => (),
+            Err(pcsc::Error::Cancelled) => return Err(Error::Cancelled),
+            Err(e) => return Err(Error::PcscError(e)),
+        }
+
+        let mut readers_changed = false;
+        let mut detached = vec![];
+
+        for state in &mut self.states {
+            let (current, event) = (state.current_state(), state.event_state());
+            state.sync_current_state();
+
+            if is_pnp(state) {
+                readers_changed |= event.contains(State::CHANGED);
+                continue;
+            }
+
+            if event.contains(State::UNKNOWN) {
+                detached.push((reader_name(state), current));
+                continue;
+            }
+
+            match (
+                current.contains(State::PRESENT),
+                event.contains(State::PRESENT),
+            ) {
+                (false, true) => self.pending.push_back(Event::CardInserted {
+                    reader: reader_name(state),
+                    atr: state.atr().to_vec(),
+                }),
+                (true, false) => self.pending.push_back(Event::CardRemoved {
+                    reader: reader_name(state),
+                }),
+                _ => (),
+            }
+        }
+
+        // Unknown readers are to be removed, otherwise the PC/SC service notifies them forever.
+        // The notification for PnP is also unknown, on platforms that do not support them.
+        self.states
+            .retain(|state| !state.event_state().contains(State::UNKNOWN));
+
+        for (reader, state) in detached {
+            self.detach(reader, state);
+        }
+
+        if readers_changed {
+            self.refresh()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for Events {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            // Nothing can be watched anymore, when all readers are detached without PnP support.
+            if self.states.is_empty() {
+                return None;
+            }
+
+            match self.poll() {
+                Ok(_) => (),
+                Err(Error::Cancelled) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+fn is_pnp(state: &ReaderState) -> bool {
+    state.name() == pcsc::PNP_NOTIFICATION()
+}
+
+fn reader_name(state: &ReaderState) -> String {
+    state.name().to_string_lossy().to_string()
+}
+
+/// A reader connected to the PC/SC service.
+#[derive(Clone, Debug)]
+pub struct Reader {
+    raw: CString,
+    pub name: String,
+    pub card_present: bool,
+}
+
+impl Reader {
+    /// Guesses whether the reader is contactless (NFC) or not, from the name of them.
+    /// As PC/SC does not tell the interface of readers, this is only a heuristic.
+    pub fn is_contactless(&self) -> bool {
+        const KEYWORDS: [&str; 6] = ["CONTACTLESS", "PICC", "NFC", "FELICA", "PASORI", " CL "];
+
+        let name = format!(" {} ", self.name.to_uppercase());
+        KEYWORDS.iter().any(|k| name.contains(k))
+    }
+}
+
+impl From<&ReaderState> for Reader {
+    fn from(state: &ReaderState) -> Self {
+        Self {
+            raw: state.name().to_owned(),
+            name: state.name().to_string_lossy().to_string(),
+            card_present: state.event_state().contains(State::PRESENT),
+        }
+    }
 }
 
 /// PC/SC device handle.
-pub struct Device<'a> {
-    reader: Box<CString>,
-    _lifetime: PhantomData<&'a ()>,
+#[derive(Clone)]
+pub struct Device {
+    reader: CString,
+    share_mode: ShareMode,
 }
 
-impl<'a> Device<'a> {
+impl Device {
     fn new(reader: &CStr) -> Self {
         debug!("Using device: {}", reader.to_str().unwrap_or_default());
 
         Self {
-            reader: Box::new(reader.to_owned()),
-            _lifetime: Default::default(),
+            reader: reader.to_owned(),
+            share_mode: ShareMode::Shared,
         }
     }
 
+    /// Sets the mode to share the card with other applications, that is shared by default.
+    /// Use [`ShareMode::Exclusive`] to prevent other applications from connecting to the card.
+    pub fn with_share_mode(mut self, share_mode: ShareMode) -> Self {
+        self.share_mode = share_mode;
+        self
+    }
+
     /// Connects to the card inserted to the device after waiting them.
-    pub fn connect(&self, ctx: Context) -> Result<PcscCard<'a>> {
-        // Waits for touching card, polling for each seconds.
-        debug!("Waiting for a card");
-
-        loop {
-            match ctx
-                .ctx
-                .connect(&self.reader, ShareMode::Shared, Protocols::ANY)
-            {
-                Ok(card) => {
-                    debug!("Connected to your card");
-
-                    return Ok(PcscCard::new(card));
-                }
-                Err(e) => match e {
-                    pcsc::Error::NoSmartcard => {
-                        info!("Still waiting for your card...");
-                        sleep(Duration::from_secs(1));
-
-                        continue;
-                    }
-                    _ => return Err(Error::PcscError(e)),
-                },
+    pub fn connect(&self, ctx: &Context) -> Result<PcscCard> {
+        self.wait(ctx, None)
+    }
+
+    /// Connects to the card inserted to the device after waiting them, up to the timeout.
+    /// Returns [`Error::Timeout`] if no card is inserted in time.
+    pub fn connect_timeout(&self, ctx: &Context, timeout: Duration) -> Result<PcscCard> {
+        self.wait(ctx, Some(Instant::now() + timeout))
+    }
+
+    fn wait(&self, ctx: &Context, deadline: Option<Instant>) -> Result<PcscCard> {
+        let card = wait_for_card(&ctx.ctx, &self.reader, self.share_mode, deadline)?;
+
+        Ok(PcscCard::new(
+            ctx.ctx.clone(),
+            self.reader.clone(),
+            self.share_mode,
+            card,
+        ))
+    }
+}
+
+/// Connects to the card on the reader after waiting them, by notifications from the PC/SC service.
+fn wait_for_card(
+    ctx: &pcsc::Context,
+    reader: &CStr,
+    share_mode: ShareMode,
+    deadline: Option<Instant>,
+) -> Result<Card> {
+    debug!("Waiting for a card");
+
+    // The first call returns immediately, as the current state is unaware.
+    let mut states = [ReaderState::new(reader, State::UNAWARE)];
+    loop {
+        let timeout = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        match ctx.get_status_change(timeout, &mut states) {
+            Ok(_) => (),
+            Err(pcsc::Error::Timeout) => return Err(Error::Timeout),
+            Err(pcsc::Error::Cancelled) => return Err(Error::Cancelled),
+            Err(e) => return Err(Error::PcscError(e)),
+        }
+
+        let state = states[0].event_state();
+        states[0].sync_current_state();
+
+        if !state.contains(State::PRESENT) || state.contains(State::MUTE) {
+            info!("Still waiting for your card...");
+            continue;
+        }
+
+        match ctx.connect(reader, share_mode, Protocols::ANY) {
+            Ok(card) => {
+                debug!("Connected to your card");
+
+                return Ok(card);
+            }
+            // The card may be removed just after detected.
+            Err(pcsc::Error::NoSmartcard | pcsc::Error::RemovedCard) => continue,
+            Err(e) => return Err(Error::PcscError(e)),
+        }
+    }
+}
+
+/// A policy to recover the connection, when the card is reset or removed during operations.
+/// On recovering, the DF and EF selected last are selected again, then the command is retried.
+/// Note that the security state such as verified PINs are not recovered, so sessions on the card
+/// end if transactions are enabled by [`crate::Card::with_transactions`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReconnectPolicy {
+    /// Returns errors without reconnecting.
+    #[default]
+    Never,
+
+    /// Reconnects when the card is reset by others.
+    OnReset,
+
+    /// Reconnects when the card is reset, or waits for the card to be presented again up to the
+    /// timeout when removed. Useful for NFC cards that are easily moved away from the reader.
+    /// The card presented again is not checked whether it is the same one.
+    OnRemoval(Duration),
+}
+
+impl ReconnectPolicy {
+    fn recovers(&self, e: pcsc::Error) -> bool {
+        matches!(
+            (self, e),
+            (Self::OnReset | Self::OnRemoval(_), pcsc::Error::ResetCard)
+                | (Self::OnRemoval(_), pcsc::Error::RemovedCard),
+        )
+    }
+}
+
+/// Commands that selected files last, to be replayed on reconnecting.
+#[derive(Clone, Default)]
+struct Selection {
+    df: Option<Vec<u8>>,
+    ef: Option<Vec<u8>>,
+}
+
+impl Selection {
+    fn remember(&mut self, tx: &[u8], rx: &[u8]) {
+        const INS_SELECT: u8 = 0xA4;
+        const P1_DF: u8 = 0x04;
+        const P1_EF: u8 = 0x02;
+
+        if !rx.ends_with(&[0x90, 0x00]) {
+            return;
+        }
+
+        match tx {
+            [_, INS_SELECT, P1_DF, ..] => {
+                self.df = Some(tx.to_vec());
+                self.ef = None;
             }
+            [_, INS_SELECT, P1_EF, ..] => self.ef = Some(tx.to_vec()),
+            _ => (),
         }
     }
+
+    fn commands(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.df.iter().chain(self.ef.iter())
+    }
 }
 
 /// A card to be communicated through PC/SC.
-pub struct PcscCard<'a> {
-    card: Card,
-    _lifetime: PhantomData<&'a ()>,
+pub struct PcscCard {
+    ctx: pcsc::Context,
+    reader: CString,
+    share_mode: ShareMode,
+    disposition: Disposition,
+    card: Mutex<Option<Card>>,
+    transaction: Mutex<Option<Ongoing>>,
+    policy: ReconnectPolicy,
+    selection: Mutex<Selection>,
+    reset: AtomicBool,
+    features: Mutex<Option<Features>>,
+    #[cfg(feature = "tracing")]
+    redactor: Mutex<Redactor>,
 }
 
-impl<'a> PcscCard<'a> {
-    fn new(card: Card) -> Self {
+// SAFETY: The pointer to the card in the ongoing transaction is dereferenced only by the thread that
+// began the transaction, while the lock of the card is held by them.
+unsafe impl Send for PcscCard {}
+unsafe impl Sync for PcscCard {}
+
+impl PcscCard {
+    fn new(ctx: pcsc::Context, reader: CString, share_mode: ShareMode, card: Card) -> Self {
         Self {
-            card,
-            _lifetime: Default::default(),
+            ctx,
+            reader,
+            share_mode,
+            disposition: Disposition::ResetCard,
+            card: Mutex::new(Some(card)),
+            transaction: Mutex::new(None),
+            policy: ReconnectPolicy::default(),
+            selection: Default::default(),
+            reset: AtomicBool::new(false),
+            features: Default::default(),
+            #[cfg(feature = "tracing")]
+            redactor: Default::default(),
         }
     }
 
+    /// Sets the policy to recover the connection, that is not to reconnect by default.
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets what to do with the card on disconnecting, that is to reset them by default.
+    /// Resetting or unpowering the card clears the verified PINs, against other applications.
+    pub fn with_disposition(mut self, disposition: Disposition) -> Self {
+        self.disposition = disposition;
+        self
+    }
+
     /// Transmits an APDU command to the card, then receives a response from them.
     pub fn transmit(&self, tx: &[u8]) -> Result<Vec<u8>> {
-        debug!("TX: {}", hex::encode(tx));
+        let rx = match self.transmit_once(tx) {
+            // The transaction is lost on reconnecting, so fails in the middle of them.
+            Err(Error::PcscError(e)) if self.policy.recovers(e) && self.ongoing().is_none() => {
+                self.reconnect(e)?;
+                self.transmit_once(tx)?
+            }
+            rx => rx?,
+        };
+
+        lock(&self.selection).remember(tx, &rx);
+
+        Ok(rx)
+    }
+
+    /// Gets the ATR of the card, to guess the kind before sending any commands.
+    pub fn atr(&self) -> Result<Atr> {
+        let status = self.with_card(|card| card.status2_owned())?;
+
+        Ok(Atr::parse(status.atr())?)
+    }
+
+    /// Queries the features of PIN pad supported by the reader.
+    pub fn pin_pad_features(&self) -> Result<Features> {
+        if let Some(features) = *lock(&self.features) {
+            return Ok(features);
+        }
+
+        let code = pcsc::ctl_code(pinpad::CM_IOCTL_GET_FEATURE_REQUEST as _);
+        let features = match self.control(code as _, &[]) {
+            Ok(buf) => Features::parse(&buf),
+            // Readers without any features may reject the request itself.
+            Err(Error::PcscError(pcsc::Error::InvalidParameter | pcsc::Error::UnsupportedCard)) => {
+                Features::default()
+            }
+            Err(e) => return Err(e),
+        };
+
+        *lock(&self.features) = Some(features);
+
+        Ok(features)
+    }
+
+    /// Sends a control command to the reader, then receives a response from them.
+    pub fn control(&self, code: u32, tx: &[u8]) -> Result<Vec<u8>> {
+        debug!("CONTROL {:08X}: {}", code, hex::encode(tx));
 
         let mut rx = [0u8; MAX_BUFFER_SIZE];
-        let rx = self.card.transmit(tx, &mut rx).map_err(Error::PcscError)?;
+        let rx = self.with_card(|card| card.control(code as _, tx, &mut rx))?;
 
         debug!("RX: {}", hex::encode(rx));
 
         Ok(Vec::from(rx))
     }
+
+    fn transmit_once(&self, tx: &[u8]) -> Result<Vec<u8>> {
+        // PINs and personal data are redacted, unless the unsafe full trace is enabled.
+        debug!("TX: {}", lock(&self.redactor).command(tx));
+
+        let mut rx = [0u8; MAX_BUFFER_SIZE];
+        let rx = self.with_card(|card| card.transmit(tx, &mut rx))?;
+
+        debug!("RX: {}", lock(&self.redactor).response(rx));
+
+        Ok(Vec::from(rx))
+    }
+
+    /// Gets the card in the transaction ongoing on the current thread, if any.
+    fn ongoing(&self) -> Option<NonNull<Card>> {
+        lock(&self.transaction)
+            .filter(|o| o.owner == thread::current().id())
+            .map(|o| o.card)
+    }
+
+    /// Calls the function with the card, or the one in the ongoing transaction if any.
+    /// Other threads wait for the transaction to end, as the lock of the card is held during them.
+    fn with_card<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&Card) -> std::result::Result<R, pcsc::Error>,
+    {
+        match self.ongoing() {
+            // SAFETY: The pointer is set only while the transaction borrowing the card is alive.
+            Some(card) => f(unsafe { card.as_ref() }),
+            None => match lock(&self.card).as_ref() {
+                Some(card) => f(card),
+                None => Err(pcsc::Error::InvalidHandle),
+            },
+        }
+        .map_err(Error::PcscError)
+    }
+
+    fn reconnect(&self, e: pcsc::Error) -> Result<()> {
+        info!("Reconnecting to your card: {}", e);
+
+        match (e, self.policy) {
+            (pcsc::Error::RemovedCard, ReconnectPolicy::OnRemoval(timeout)) => {
+                let card = wait_for_card(
+                    &self.ctx,
+                    &self.reader,
+                    self.share_mode,
+                    Some(Instant::now() + timeout),
+                )?;
+                *lock(&self.card) = Some(card);
+            }
+            _ => lock(&self.card)
+                .as_mut()
+                .ok_or(pcsc::Error::InvalidHandle)
+                .and_then(|card| {
+                    card.reconnect(self.share_mode, Protocols::ANY, Disposition::LeaveCard)
+                })
+                .map_err(Error::PcscError)?,
+        }
+
+        // The security status is lost, so sessions on the card must end.
+        self.reset.store(true, Ordering::Relaxed);
+
+        // Failures are reported by the retried command, as the selection is required for them.
+        let selection = lock(&self.selection).clone();
+        for command in selection.commands() {
+            self.transmit_once(command)?;
+        }
+
+        Ok(())
+    }
+
+    fn transaction<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce() -> R,
+    {
+        // Transactions are not nested, the outermost one covers the inner ones.
+        if self.ongoing().is_some() {
+            return Ok(f());
+        }
+
+        let mut card = lock(&self.card);
+        let card = card.as_mut().ok_or(pcsc::Error::InvalidHandle)?;
+        let transaction = card.transaction().map_err(Error::PcscError)?;
+
+        debug!("Began a transaction");
+
+        // Commands in the function are transmitted through the transaction, until the guard drops.
+        let guard = TransactionGuard::new(&self.transaction, &transaction);
+        let result = f();
+        drop(guard);
+
+        transaction
+            .end(Disposition::LeaveCard)
+            .map_err(|(_, e)| Error::PcscError(e))?;
+
+        debug!("Ended the transaction");
+
+        Ok(result)
+    }
+}
+
+impl Drop for PcscCard {
+    fn drop(&mut self) {
+        let card = self.card.get_mut().unwrap_or_else(PoisonError::into_inner);
+        if let Some(card) = card.take() {
+            if let Err((_, _e)) = card.disconnect(self.disposition) {
+                info!("Failed to disconnect from your card: {}", _e);
+            }
+        }
+    }
+}
+
+/// The card in the ongoing transaction, with the thread that began them.
+#[derive(Clone, Copy)]
+struct Ongoing {
+    owner: ThreadId,
+    card: NonNull<Card>,
+}
+
+/// Points the card in the ongoing transaction, and clears them when dropped, even on panics.
+struct TransactionGuard<'t> {
+    slot: &'t Mutex<Option<Ongoing>>,
+}
+
+impl<'t> TransactionGuard<'t> {
+    fn new(slot: &'t Mutex<Option<Ongoing>>, card: &'t Card) -> Self {
+        *lock(slot) = Some(Ongoing {
+            owner: thread::current().id(),
+            card: NonNull::from(card),
+        });
+
+        Self { slot }
+    }
+}
+
+impl<'t> Drop for TransactionGuard<'t> {
+    fn drop(&mut self) {
+        *lock(self.slot) = None;
+    }
 }
 
 type Ctx = ();
 
-impl<'a> HandlerInCtx<Ctx> for PcscCard<'a> {
+impl HandlerInCtx<Ctx> for PcscCard {
     fn handle_in_ctx(
         &self,
         _: Ctx,
         command: &[u8],
         mut response: &mut [u8],
     ) -> std::result::Result<usize, HandleError> {
-        let tx = Vec::from(command);
-        let rx = self.transmit(&tx).unwrap();
+        let rx = self
+            .transmit(command)
+            .map_err(|e| HandleError::Nfc(Box::new(e)))?;
         let len = rx.len();
         if response.len() < len {
             return Err(HandleError::NotEnoughBuffer(len));
@@ -187,3 +867,42 @@ impl<'a> HandlerInCtx<Ctx> for PcscCard<'a> {
         }
     }
 }
+
+impl PinPad<Ctx> for PcscCard {
+    fn verify_pin_on_pad(
+        &self,
+        _: Ctx,
+        format: &PinFormat,
+        header: [u8; 4],
+    ) -> std::result::Result<Vec<u8>, HandleError> {
+        self.pin_pad_features()
+            .and_then(|f| f.verify_pin_direct.ok_or(Error::PinPadNotSupported))
+            .and_then(|code| self.control(code, &pinpad::verify_structure(format, header)))
+            .map_err(|e| HandleError::Nfc(Box::new(e)))
+    }
+
+    fn modify_pin_on_pad(
+        &self,
+        _: Ctx,
+        format: &PinFormat,
+        header: [u8; 4],
+    ) -> std::result::Result<Vec<u8>, HandleError> {
+        self.pin_pad_features()
+            .and_then(|f| f.modify_pin_direct.ok_or(Error::PinPadNotSupported))
+            .and_then(|code| self.control(code, &pinpad::modify_structure(format, header)))
+            .map_err(|e| HandleError::Nfc(Box::new(e)))
+    }
+}
+
+impl Transactional<Ctx> for PcscCard {
+    fn transaction<R, F>(&self, _: Ctx, f: F) -> std::result::Result<R, HandleError>
+    where
+        F: FnOnce() -> R,
+    {
+        PcscCard::transaction(self, f).map_err(|e| HandleError::Nfc(Box::new(e)))
+    }
+
+    fn take_reset(&self) -> bool {
+        self.reset.swap(false, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,257 @@
+//! Secure PIN entry on readers with a PIN pad, as defined in PC/SC part 10.
+//! The PIN is entered on the reader and sent to the card directly, never passing the host.
+//!
+//! This module only encodes and decodes the control structures,
+//! so they can be used by any transports that support PIN pads.
+//! The formats are also used to validate PINs entered on the host, before sent to the card.
+
+use std::fmt::{Display, Formatter};
+
+/// Feature tag to verify the PIN using `PIN_VERIFY_STRUCTURE`.
+pub const FEATURE_VERIFY_PIN_DIRECT: u8 = 0x06;
+
+/// Feature tag to change the PIN using `PIN_MODIFY_STRUCTURE`.
+pub const FEATURE_MODIFY_PIN_DIRECT: u8 = 0x07;
+
+/// Control code to query the features supported by the reader, to be passed to `SCARD_CTL_CODE`.
+pub const CM_IOCTL_GET_FEATURE_REQUEST: u32 = 3400;
+
+/// PINs are in ASCII, left justified, and the system units are bytes.
+const FORMAT_STRING_ASCII: u8 = 0x82;
+
+/// Validates the PIN when the OK button is pressed.
+const ENTRY_VALIDATION_OK_BUTTON: u8 = 0x02;
+
+/// The new PIN is entered twice to confirm, without the current PIN.
+const CONFIRM_NEW_PIN: u8 = 0x01;
+
+const LANG_ID_EN_US: u16 = 0x0409;
+
+/// PIN is malformed, so it was not sent to the card not to waste the retry count.
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidPin {
+    #[error("The PIN must be {0}")]
+    Format(PinFormat),
+
+    #[error("The PIN must start with a valid date in 'YYMMDD' format")]
+    Date,
+}
+
+/// Characters allowed in PINs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Charset {
+    /// Digits only.
+    Digits,
+
+    /// Uppercase letters and digits.
+    UppercaseAlphanumerics,
+}
+
+impl Charset {
+    fn contains(self, c: u8) -> bool {
+        match self {
+            Self::Digits => c.is_ascii_digit(),
+            Self::UppercaseAlphanumerics => c.is_ascii_uppercase() || c.is_ascii_digit(),
+        }
+    }
+}
+
+/// Length and characters of PINs accepted by the EF, to be told to the reader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinFormat {
+    pub min_len: u8,
+    pub max_len: u8,
+    pub charset: Charset,
+}
+
+impl PinFormat {
+    /// Creates the format of PINs in digits.
+    pub const fn new(min_len: u8, max_len: u8) -> Self {
+        Self {
+            min_len,
+            max_len,
+            charset: Charset::Digits,
+        }
+    }
+
+    /// Sets the characters allowed in PINs.
+    pub const fn with_charset(mut self, charset: Charset) -> Self {
+        self.charset = charset;
+        self
+    }
+
+    /// Validates the PIN against the format.
+    pub fn validate(&self, pin: &[u8]) -> Result<(), InvalidPin> {
+        let len = self.min_len as usize..=self.max_len as usize;
+        if !len.contains(&pin.len()) || !pin.iter().all(|c| self.charset.contains(*c)) {
+            return Err(InvalidPin::Format(*self));
+        }
+
+        Ok(())
+    }
+
+    /// Encodes `wPINMaxExtraDigit`, that holds the minimum and the maximum length.
+    fn extra_digit(&self) -> [u8; 2] {
+        u16::from_be_bytes([self.min_len, self.max_len]).to_le_bytes()
+    }
+}
+
+impl Display for PinFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.min_len == self.max_len {
+            true => write!(f, "{}", self.min_len)?,
+            _ => write!(f, "{} to {}", self.min_len, self.max_len)?,
+        }
+
+        match self.charset {
+            Charset::Digits => write!(f, " digits"),
+            Charset::UppercaseAlphanumerics => write!(f, " uppercase letters or digits"),
+        }
+    }
+}
+
+/// Control codes of the features supported by the reader.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Features {
+    pub verify_pin_direct: Option<u32>,
+    pub modify_pin_direct: Option<u32>,
+}
+
+impl Features {
+    /// Decodes the TLV response of `CM_IOCTL_GET_FEATURE_REQUEST`.
+    pub fn parse(buf: &[u8]) -> Self {
+        let mut features = Self::default();
+
+        for tlv in buf.chunks_exact(6) {
+            if tlv[1] != 4 {
+                continue;
+            }
+
+            let code = Some(u32::from_be_bytes([tlv[2], tlv[3], tlv[4], tlv[5]]));
+            match tlv[0] {
+                FEATURE_VERIFY_PIN_DIRECT => features.verify_pin_direct = code,
+                FEATURE_MODIFY_PIN_DIRECT => features.modify_pin_direct = code,
+                _ => (),
+            }
+        }
+
+        features
+    }
+}
+
+/// Encodes `PIN_VERIFY_STRUCTURE` to send the command with the PIN entered.
+/// The command is an APDU header, that the PIN is appended to.
+pub fn verify_structure(format: &PinFormat, header: [u8; 4]) -> Vec<u8> {
+    let data = apdu_template(header);
+    let mut buf = vec![
+        0x00,                // bTimerOut: default
+        0x00,                // bTimerOut2: default
+        FORMAT_STRING_ASCII, // bmFormatString
+        0x00,                // bmPINBlockString: the reader sets the length
+        0x00,                // bmPINLengthFormat: no length in the PIN block
+    ];
+
+    buf.extend_from_slice(&format.extra_digit());
+    buf.push(ENTRY_VALIDATION_OK_BUTTON);
+    buf.push(0x01); // bNumberMessage
+    buf.extend_from_slice(&LANG_ID_EN_US.to_le_bytes());
+    buf.push(0x00); // bMsgIndex
+    buf.extend_from_slice(&[0x00; 3]); // bTeoPrologue
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&data);
+    buf
+}
+
+/// Encodes `PIN_MODIFY_STRUCTURE` to send the command with the new PIN entered twice.
+/// The command is an APDU header, that the new PIN is appended to.
+pub fn modify_structure(format: &PinFormat, header: [u8; 4]) -> Vec<u8> {
+    let data = apdu_template(header);
+    let mut buf = vec![
+        0x00,                // bTimerOut: default
+        0x00,                // bTimerOut2: default
+        FORMAT_STRING_ASCII, // bmFormatString
+        0x00,                // bmPINBlockString: the reader sets the length
+        0x00,                // bmPINLengthFormat: no length in the PIN block
+        0x00,                // bInsertionOffsetOld: unused
+        0x00,                // bInsertionOffsetNew
+    ];
+
+    buf.extend_from_slice(&format.extra_digit());
+    buf.push(CONFIRM_NEW_PIN);
+    buf.push(ENTRY_VALIDATION_OK_BUTTON);
+    buf.push(0x02); // bNumberMessage: new PIN and confirmation
+    buf.extend_from_slice(&LANG_ID_EN_US.to_le_bytes());
+    buf.extend_from_slice(&[0x00, 0x01, 0x02]); // bMsgIndex1-3
+    buf.extend_from_slice(&[0x00; 3]); // bTeoPrologue
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&data);
+    buf
+}
+
+/// Makes the APDU with an empty body, whose Lc is filled by the reader.
+fn apdu_template(header: [u8; 4]) -> Vec<u8> {
+    let mut data = header.to_vec();
+    data.push(0x00);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_structure() {
+        assert_eq!(
+            vec![
+                0x00, 0x00, 0x82, 0x00, 0x00, 0x10, 0x06, 0x02, 0x01, 0x09, 0x04, 0x00, 0x00, 0x00,
+                0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x80, 0x00,
+            ],
+            verify_structure(&PinFormat::new(6, 16), [0x00, 0x20, 0x00, 0x80]),
+        );
+    }
+
+    #[test]
+    fn test_modify_structure() {
+        assert_eq!(
+            vec![
+                0x00, 0x00, 0x82, 0x00, 0x00, 0x00, 0x00, 0x04, 0x04, 0x01, 0x02, 0x02, 0x09, 0x04,
+                0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x24, 0x01, 0x80,
+                0x00,
+            ],
+            modify_structure(&PinFormat::new(4, 4), [0x00, 0x24, 0x01, 0x80]),
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let format = PinFormat::new(6, 16).with_charset(Charset::UppercaseAlphanumerics);
+        assert!(format.validate(b"ABC123").is_ok());
+        assert!(format.validate(b"abc123").is_err());
+        assert!(format.validate(b"ABC12").is_err());
+        assert_eq!(
+            "The PIN must be 6 to 16 uppercase letters or digits",
+            format.validate(b"").unwrap_err().to_string(),
+        );
+
+        let format = PinFormat::new(4, 4);
+        assert!(format.validate(b"1234").is_ok());
+        assert!(format.validate(b"123").is_err());
+        assert!(format.validate(b"123A").is_err());
+    }
+
+    #[test]
+    fn test_features() {
+        let buf = [
+            0x06, 0x04, 0x42, 0x33, 0x00, 0x06, 0x07, 0x04, 0x42, 0x33, 0x00, 0x07, 0x12, 0x04,
+            0x42, 0x33, 0x00, 0x12,
+        ];
+
+        assert_eq!(
+            Features {
+                verify_pin_direct: Some(0x42330006),
+                modify_pin_direct: Some(0x42330007),
+            },
+            Features::parse(&buf),
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Redacting PINs and personal data from APDUs, before logging or capturing them.
+//!
+//! Commands are classified by the instruction and the selected file: data of VERIFY and CHANGE
+//! REFERENCE DATA commands are masked, and responses of READ BINARY on files with personal data
+//! are elided except the status word.
+//! Set [`set_unsafe_full_trace`] to disable the redaction for debugging, at your own risk.
+
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::ap::crypto::CertType;
+use crate::ap::{crypto, support, surface};
+
+const INS_SELECT: u8 = 0xA4;
+const INS_VERIFY: u8 = 0x20;
+const INS_CHANGE: u8 = 0x24;
+const INS_READ_BINARY: u8 = 0xB0;
+const SELECT_P1_DF: u8 = 0x04;
+const SELECT_P1_EF: u8 = 0x02;
+
+/// Length of the header and Lc, that precede the data in commands.
+const DATA_OFFSET: usize = 5;
+
+/// Length of the status word, that follows the data in responses.
+const SW_LEN: usize = 2;
+
+static UNSAFE_FULL_TRACE: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether to trace APDUs as is, without redacting anything.
+/// Never enable this in production, as the PINs and the personal data are leaked into the logs.
+pub fn set_unsafe_full_trace(enabled: bool) {
+    UNSAFE_FULL_TRACE.store(enabled, Ordering::Relaxed);
+}
+
+/// Determines whether to trace APDUs as is.
+pub fn is_unsafe_full_trace() -> bool {
+    UNSAFE_FULL_TRACE.load(Ordering::Relaxed)
+}
+
+/// An APDU redacted to be logged or captured.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redacted {
+    /// The APDU, whose masked bytes are zeroed and elided bytes are removed.
+    pub apdu: Vec<u8>,
+    masked: Range<usize>,
+    elided: usize,
+}
+
+impl Redacted {
+    fn as_is(apdu: &[u8]) -> Self {
+        Self {
+            apdu: apdu.to_vec(),
+            masked: 0..0,
+            elided: 0,
+        }
+    }
+
+    /// Gets the octets of the APDU, that are `None` if masked.
+    pub(crate) fn octets(&self) -> Vec<Option<u8>> {
+        self.apdu
+            .iter()
+            .enumerate()
+            .map(|(i, b)| match self.masked.contains(&i) {
+                true => None,
+                _ => Some(*b),
+            })
+            .collect()
+    }
+}
+
+impl Display for Redacted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.elided > 0 {
+            write!(f, "({} octets elided)", self.elided)?;
+        }
+
+        for (i, b) in self.apdu.iter().enumerate() {
+            match self.masked.contains(&i) {
+                true => write!(f, "**")?,
+                _ => write!(f, "{:02X}", b)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Redacts the APDUs exchanged with a card, following the files selected by the commands.
+#[derive(Clone, Debug, Default)]
+pub struct Redactor {
+    df: Option<Vec<u8>>,
+    ef: Option<Vec<u8>>,
+    personal_response: bool,
+}
+
+impl Redactor {
+    /// Redacts the command, remembering the files selected.
+    pub fn command(&mut self, apdu: &[u8]) -> Redacted {
+        self.personal_response = false;
+
+        let redacted = match apdu {
+            [_, INS_SELECT, p1, ..] => {
+                let name = apdu.get(DATA_OFFSET..).unwrap_or_default().to_vec();
+                match *p1 {
+                    SELECT_P1_DF => {
+                        self.df = Some(name);
+                        self.ef = None;
+                    }
+                    SELECT_P1_EF => self.ef = Some(name),
+                    _ => (),
+                }
+
+                Redacted::as_is(apdu)
+            }
+            [_, INS_VERIFY | INS_CHANGE, ..] if apdu.len() > DATA_OFFSET => Redacted {
+                apdu: apdu
+                    .iter()
+                    .enumerate()
+                    .map(|(i, b)| if i < DATA_OFFSET { *b } else { 0 })
+                    .collect(),
+                masked: DATA_OFFSET..apdu.len(),
+                elided: 0,
+            },
+            [_, INS_READ_BINARY, ..] => {
+                self.personal_response = self.is_personal();
+                Redacted::as_is(apdu)
+            }
+            _ => Redacted::as_is(apdu),
+        };
+
+        match is_unsafe_full_trace() {
+            true => Redacted::as_is(apdu),
+            _ => redacted,
+        }
+    }
+
+    /// Redacts the response to the last command.
+    pub fn response(&mut self, apdu: &[u8]) -> Redacted {
+        let personal = std::mem::take(&mut self.personal_response);
+        if !personal || is_unsafe_full_trace() || apdu.len() <= SW_LEN {
+            return Redacted::as_is(apdu);
+        }
+
+        let (data, sw) = apdu.split_at(apdu.len() - SW_LEN);
+
+        Redacted {
+            apdu: sw.to_vec(),
+            masked: 0..0,
+            elided: data.len(),
+        }
+    }
+
+    /// Determines whether the selected file contains personal data, such as the name, the address
+    /// and the My Number.
+    fn is_personal(&self) -> bool {
+        let Some(df) = self.df.as_deref() else {
+            // Files are unknown, so they can contain anything.
+            return true;
+        };
+
+        if df == surface::DF_NAME || df == support::DF_NAME {
+            return true;
+        }
+
+        // Certificates for signing contain the name, the address and the DoB.
+        df == crypto::DF_NAME && self.ef.as_deref() == Some(&CertType::Sign.into_efid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_verify() {
+        let mut redactor = Redactor::default();
+        let redacted = redactor.command(&[0x00, 0x20, 0x00, 0x80, 0x04, 0x31, 0x32, 0x33, 0x34]);
+
+        assert_eq!(
+            vec![0x00, 0x20, 0x00, 0x80, 0x04, 0x00, 0x00, 0x00, 0x00],
+            redacted.apdu
+        );
+        assert_eq!("0020008004********", redacted.to_string());
+
+        // Queries of the status have no data to mask.
+        let redacted = redactor.command(&[0x00, 0x20, 0x00, 0x80]);
+        assert_eq!("00200080", redacted.to_string());
+    }
+
+    #[test]
+    fn test_redact_read_binary() {
+        let mut redactor = Redactor::default();
+        let mut select = vec![0x00, 0xA4, 0x04, 0x0C, 0x0A];
+        select.extend_from_slice(&crypto::DF_NAME);
+        redactor.command(&select);
+
+        // Certificates for authentication have no personal data.
+        redactor.command(&[0x00, 0xA4, 0x02, 0x0C, 0x02, 0x00, 0x0A]);
+        redactor.command(&[0x00, 0xB0, 0x00, 0x00, 0x02]);
+        assert_eq!(
+            "30829000",
+            redactor.response(&[0x30, 0x82, 0x90, 0x00]).to_string()
+        );
+
+        redactor.command(&[0x00, 0xA4, 0x02, 0x0C, 0x02, 0x00, 0x01]);
+        redactor.command(&[0x00, 0xB0, 0x00, 0x00, 0x02]);
+        let redacted = redactor.response(&[0x30, 0x82, 0x90, 0x00]);
+        assert_eq!(vec![0x90, 0x00], redacted.apdu);
+        assert_eq!("(2 octets elided)9000", redacted.to_string());
+    }
+}
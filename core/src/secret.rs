@@ -0,0 +1,44 @@
+//! Secrets that are wiped from the memory on dropped, such as PINs.
+
+use zeroize::Zeroizing;
+
+/// A PIN to be verified by the card, that is zeroized on dropped.
+/// Neither [`std::fmt::Debug`] nor [`std::fmt::Display`] is implemented, not to leak them into logs.
+///
+/// Construct them from the owned buffer, as copies made before are out of control.
+#[derive(Clone, Default)]
+pub struct SecretPin(Zeroizing<Vec<u8>>);
+
+impl SecretPin {
+    /// Wraps the PIN, taking the ownership of the buffer.
+    pub fn new(pin: Vec<u8>) -> Self {
+        Self(Zeroizing::new(pin))
+    }
+
+    /// Exposes the PIN, to be sent to the card.
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Gets the length of the PIN in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Determines whether the PIN is empty, that is used to query the status.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<u8>> for SecretPin {
+    fn from(pin: Vec<u8>) -> Self {
+        Self::new(pin)
+    }
+}
+
+impl From<String> for SecretPin {
+    fn from(pin: String) -> Self {
+        Self::new(pin.into_bytes())
+    }
+}
@@ -0,0 +1,215 @@
+//! Sessions that verify the PIN once, then reuse the security status on the card.
+//!
+//! The card keeps the PIN verified until another DF is selected or the card is reset.
+//! A session tracks the status, so multiple operations such as reading the certificate and signing
+//! can be run with a single VERIFY.
+//! Sessions end on [`Session::logout`], timing out, or selecting another DF by any AP sharing the
+//! card, after which the operations fail with [`crate::card::Error::SessionEnded`].
+//! Resetting the card, such as reconnecting on PC/SC, ends the sessions too if the delegate reports
+//! them, with transactions enabled by [`crate::Card::with_transactions`].
+
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::card::Error;
+use crate::secret::SecretPin;
+use crate::{nfc, Card};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A PIN verified on the card, whose security status is reused while the DF stays selected.
+pub struct Session<T, Ctx, C = Rc<Card<T, Ctx>>>
+where
+    T: nfc::HandlerInCtx<Ctx>,
+    Ctx: Copy,
+    C: Deref<Target = Card<T, Ctx>>,
+{
+    card: C,
+    id: u64,
+    df: Vec<u8>,
+    ef: [u8; 2],
+    expires_at: Option<Instant>,
+}
+
+impl<T, Ctx, C> Session<T, Ctx, C>
+where
+    T: nfc::HandlerInCtx<Ctx>,
+    Ctx: Copy,
+    C: Deref<Target = Card<T, Ctx>>,
+{
+    /// Verifies the PIN using the EF in the DF, then begins the session.
+    /// Without the timeout, the session lasts until the DF is deselected.
+    pub fn login(
+        ctx: Ctx,
+        card: C,
+        df: &[u8],
+        ef: [u8; 2],
+        pin: &SecretPin,
+        timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        card.begin_session(ctx, df, ef, pin, id)?;
+
+        Ok(Self {
+            card,
+            id,
+            df: df.to_vec(),
+            ef,
+            expires_at: timeout.map(|t| Instant::now() + t),
+        })
+    }
+
+    /// Determines whether the PIN is still verified on the card.
+    pub fn is_active(&self) -> bool {
+        !self.is_expired() && self.card.is_in_session(&self.df, self.id)
+    }
+
+    /// Runs the operation in the DF with the PIN of the EF verified.
+    /// Fails if the session has ended, logging out if timed out.
+    pub fn run<R, F>(&self, ctx: Ctx, df: &[u8], ef: [u8; 2], f: F) -> Result<R, Error>
+    where
+        F: FnOnce() -> Result<R, Error>,
+    {
+        if self.df != df || self.ef != ef {
+            return Err(Error::SessionEnded);
+        }
+
+        if self.is_expired() {
+            self.card.end_session(ctx, &self.df, self.id)?;
+
+            return Err(Error::SessionEnded);
+        }
+
+        self.card.transaction_in_session(ctx, &self.df, self.id, f)
+    }
+
+    /// Ends the session, resetting the security status on the card.
+    /// Note that other sessions in the same DF end too.
+    pub fn logout(self, ctx: Ctx) -> Result<(), Error> {
+        self.card.end_session(ctx, &self.df, self.id)
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|expires_at| Instant::now() >= expires_at)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::nfc::{HandleError, HandlerInCtx, Transactional};
+    use crate::transcript::Replay;
+
+    const TRANSCRIPT: &str = r#"
+> 00A4040C03010203
+< 9000
+> 00A4020C020011
+< 9000
+> 0020008004XXXXXXXX
+< 9000
+> 00A4040C03040506
+< 9000
+"#;
+
+    const RESET_TRANSCRIPT: &str = r#"
+> 00A4040C03010203
+< 9000
+> 00A4020C020011
+< 9000
+> 0020008004XXXXXXXX
+< 9000
+> 00A4020C020001
+< 9000
+> 00A4040C03010203
+< 9000
+"#;
+
+    #[test]
+    fn test_session() {
+        let replay = Replay::parse(TRANSCRIPT).unwrap();
+        let card = Arc::new(Card::new(Box::new(replay)));
+        let pin = SecretPin::new(b"1234".to_vec());
+
+        let df = [0x01, 0x02, 0x03];
+        let session = Session::login((), Arc::clone(&card), &df, [0x00, 0x11], &pin, None).unwrap();
+        assert!(session.is_active());
+        assert!(matches!(
+            session.run((), &df, [0x00, 0x11], || Ok(1)),
+            Ok(1)
+        ));
+        assert!(matches!(
+            session.run((), &df, [0x00, 0x12], || Ok(1)),
+            Err(Error::SessionEnded),
+        ));
+
+        card.select_df((), vec![0x04, 0x05, 0x06]).unwrap();
+        assert!(!session.is_active());
+        assert!(matches!(
+            session.run((), &df, [0x00, 0x11], || Ok(1)),
+            Err(Error::SessionEnded),
+        ));
+    }
+
+    /// Replays the transcript, reporting that the card has been reset when told.
+    struct Resetting {
+        replay: Arc<Replay>,
+        reset: Arc<AtomicBool>,
+    }
+
+    impl HandlerInCtx for Resetting {
+        fn handle_in_ctx(
+            &self,
+            ctx: (),
+            command: &[u8],
+            response: &mut [u8],
+        ) -> Result<usize, HandleError> {
+            self.replay.handle_in_ctx(ctx, command, response)
+        }
+    }
+
+    impl Transactional for Resetting {
+        fn take_reset(&self) -> bool {
+            self.reset.swap(false, Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn test_session_reset() {
+        let replay = Arc::new(Replay::parse(RESET_TRANSCRIPT).unwrap());
+        let reset = Arc::new(AtomicBool::new(false));
+        let card = Arc::new(
+            Card::new(Box::new(Resetting {
+                replay: Arc::clone(&replay),
+                reset: Arc::clone(&reset),
+            }))
+            .with_transactions(),
+        );
+        let pin = SecretPin::new(b"1234".to_vec());
+
+        let df = [0x01, 0x02, 0x03];
+        let session = Session::login((), Arc::clone(&card), &df, [0x00, 0x11], &pin, None).unwrap();
+
+        // The card is reset while sending the command in the session.
+        reset.store(true, Ordering::Relaxed);
+        assert!(session
+            .run((), &df, [0x00, 0x11], || card
+                .select_ef((), vec![0x00, 0x01]))
+            .is_ok());
+        assert!(!session.is_active());
+        assert!(matches!(
+            session.run((), &df, [0x00, 0x11], || Ok(1)),
+            Err(Error::SessionEnded),
+        ));
+
+        // The DF is selected again, as the card may have forgotten them.
+        card.transaction_in((), &df, || Ok(())).unwrap();
+        assert_eq!(0, replay.remaining());
+    }
+}
@@ -0,0 +1,105 @@
+//! Synchronization primitives to share cards across threads.
+
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, ThreadId};
+
+/// Locks the mutex, ignoring poisoning as the states are kept consistent even on panics.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A lock that can be acquired again by the thread holding them, to nest operations on the card.
+#[derive(Default)]
+pub(crate) struct ReentrantLock {
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    owner: Option<ThreadId>,
+    depth: usize,
+}
+
+impl ReentrantLock {
+    /// Acquires the lock, waiting for other threads to release them.
+    pub(crate) fn acquire(&self) -> ReentrantGuard<'_> {
+        let current = thread::current().id();
+        let mut state = lock(&self.state);
+        while state.owner.is_some_and(|owner| owner != current) {
+            state = self
+                .released
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+
+        state.owner = Some(current);
+        state.depth += 1;
+
+        ReentrantGuard {
+            lock: self,
+            nested: state.depth > 1,
+        }
+    }
+}
+
+/// Releases the lock when dropped.
+pub(crate) struct ReentrantGuard<'l> {
+    lock: &'l ReentrantLock,
+    nested: bool,
+}
+
+impl<'l> ReentrantGuard<'l> {
+    /// Determines whether the lock was already held by the thread, on acquiring.
+    pub(crate) fn is_nested(&self) -> bool {
+        self.nested
+    }
+}
+
+impl<'l> Drop for ReentrantGuard<'l> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.lock.state);
+        state.depth -= 1;
+
+        if state.depth == 0 {
+            state.owner = None;
+            self.lock.released.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_reentrant_lock() {
+        let lock = Arc::new(ReentrantLock::default());
+        let entered = Arc::new(AtomicBool::new(false));
+
+        let outer = lock.acquire();
+        let inner = lock.acquire();
+        assert!(!outer.is_nested());
+        assert!(inner.is_nested());
+
+        let handle = {
+            let lock = Arc::clone(&lock);
+            let entered = Arc::clone(&entered);
+
+            thread::spawn(move || {
+                let _guard = lock.acquire();
+                entered.store(true, Ordering::SeqCst);
+            })
+        };
+
+        drop(inner);
+        assert!(!entered.load(Ordering::SeqCst));
+
+        drop(outer);
+        handle.join().unwrap();
+        assert!(entered.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,502 @@
+//! Recording APDU transcripts from the card, and replaying them without the card.
+//!
+//! A transcript is a text file of command and response pairs in hex:
+//! ```text
+//! # Comments are ignored.
+//! > 00A4040C0AD3921000310001010408
+//! < 9000
+//! ```
+//! Bytes in commands can be redacted as `XX`, such as PINs in VERIFY commands,
+//! that match any bytes on replaying.
+//! PINs and personal data are redacted on recording as in [`crate::redact`], unless the unsafe
+//! full trace is enabled.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::nfc::{HandleError, HandlerInCtx, Transactional};
+use crate::redact::Redactor;
+use crate::sync::lock;
+
+const COMMAND_PREFIX: &str = "> ";
+const RESPONSE_PREFIX: &str = "< ";
+const COMMENT_PREFIX: char = '#';
+const REDACTED: &str = "XX";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("I/O error occurred: {0}")]
+    IO(#[from] std::io::Error),
+
+    #[error("Invalid line in the transcript at line {0}")]
+    InvalidLine(usize),
+
+    #[error("A response without the command at line {0}")]
+    UnexpectedResponse(usize),
+
+    #[error("The command has no response at line {0}")]
+    MissingResponse(usize),
+
+    #[error("Unexpected command: {0}")]
+    UnexpectedCommand(String),
+
+    #[error("Command mismatch: expected {expected}, got {actual}")]
+    CommandMismatch { expected: String, actual: String },
+}
+
+/// A pair of the command and the response, in a transcript.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Exchange {
+    /// Bytes of the command, that are `None` if redacted.
+    pub command: Vec<Option<u8>>,
+    pub response: Vec<u8>,
+}
+
+impl Exchange {
+    /// Determines whether the command matches, ignoring the redacted bytes.
+    pub fn matches(&self, command: &[u8]) -> bool {
+        self.command.len() == command.len()
+            && self
+                .command
+                .iter()
+                .zip(command)
+                .all(|(expected, actual)| expected.is_none() || *expected == Some(*actual))
+    }
+}
+
+/// A handler that records every command and response through the delegate into the writer.
+/// PINs in the commands are recorded as redacted, and personal data in the responses are elided
+/// except the status word.
+pub struct Recorder<T, W>
+where
+    W: Write,
+{
+    delegate: T,
+    writer: Mutex<(W, Redactor)>,
+}
+
+impl<T> Recorder<T, BufWriter<File>> {
+    /// Records the transcript into the file, that is created or truncated.
+    pub fn create<P: AsRef<Path>>(delegate: T, path: P) -> Result<Self, Error> {
+        Ok(Self::new(delegate, BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<T, W> Recorder<T, W>
+where
+    W: Write,
+{
+    /// Records the transcript into the writer.
+    pub fn new(delegate: T, writer: W) -> Self {
+        Self {
+            delegate,
+            writer: Mutex::new((writer, Redactor::default())),
+        }
+    }
+
+    /// Gets the delegate and the writer back, flushing the writer.
+    pub fn into_inner(self) -> Result<(T, W), Error> {
+        let (mut writer, _) = self.writer.into_inner().unwrap_or_else(|e| e.into_inner());
+        writer.flush()?;
+
+        Ok((self.delegate, writer))
+    }
+
+    fn record(&self, command: &[u8], response: &[u8]) -> std::io::Result<()> {
+        let (writer, redactor) = &mut *lock(&self.writer);
+        let command = redactor.command(command);
+        let response = redactor.response(response);
+
+        writeln!(
+            writer,
+            "{}{}",
+            COMMAND_PREFIX,
+            encode_redacted(&command.octets()),
+        )?;
+        writeln!(writer, "{}{}", RESPONSE_PREFIX, encode(&response.apdu))?;
+        writer.flush()
+    }
+}
+
+impl<T, W, Ctx> HandlerInCtx<Ctx> for Recorder<T, W>
+where
+    T: HandlerInCtx<Ctx>,
+    W: Write,
+{
+    fn handle_in_ctx(
+        &self,
+        ctx: Ctx,
+        command: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, HandleError> {
+        let len = self.delegate.handle_in_ctx(ctx, command, response)?;
+
+        self.record(command, &response[..len])
+            .map_err(|e| HandleError::Nfc(Box::new(e)))?;
+
+        Ok(len)
+    }
+}
+
+impl<T, W, Ctx> Transactional<Ctx> for Recorder<T, W>
+where
+    T: Transactional<Ctx>,
+    W: Write,
+{
+    fn transaction<R, F>(&self, ctx: Ctx, f: F) -> Result<R, HandleError>
+    where
+        F: FnOnce() -> R,
+    {
+        self.delegate.transaction(ctx, f)
+    }
+
+    fn take_reset(&self) -> bool {
+        self.delegate.take_reset()
+    }
+}
+
+/// A handler that serves the responses in the transcript, without the card.
+/// Fails if the commands do not match the transcript, to be used in tests.
+pub struct Replay {
+    exchanges: Mutex<VecDeque<Exchange>>,
+}
+
+impl Replay {
+    /// Replays the exchanges in order.
+    pub fn new(exchanges: Vec<Exchange>) -> Self {
+        Self {
+            exchanges: Mutex::new(exchanges.into()),
+        }
+    }
+
+    /// Replays the transcript in the file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Replays the transcript in the text.
+    pub fn parse(transcript: &str) -> Result<Self, Error> {
+        let mut exchanges = vec![];
+        let mut command: Option<(usize, Vec<Option<u8>>)> = None;
+
+        for (i, line) in transcript.lines().enumerate() {
+            let n = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(COMMENT_PREFIX) {
+                continue;
+            }
+
+            if let Some(hex) = line.strip_prefix(COMMAND_PREFIX) {
+                if let Some((n, _)) = command {
+                    return Err(Error::MissingResponse(n));
+                }
+
+                command = Some((n, decode(hex).ok_or(Error::InvalidLine(n))?));
+            } else if let Some(hex) = line.strip_prefix(RESPONSE_PREFIX) {
+                let (_, command) = command.take().ok_or(Error::UnexpectedResponse(n))?;
+                let response = decode(hex)
+                    .and_then(|r| r.into_iter().collect::<Option<Vec<_>>>())
+                    .ok_or(Error::InvalidLine(n))?;
+
+                exchanges.push(Exchange { command, response });
+            } else {
+                return Err(Error::InvalidLine(n));
+            }
+        }
+
+        match command {
+            Some((n, _)) => Err(Error::MissingResponse(n)),
+            None => Ok(Self::new(exchanges)),
+        }
+    }
+
+    /// Gets the number of exchanges not replayed yet.
+    pub fn remaining(&self) -> usize {
+        lock(&self.exchanges).len()
+    }
+}
+
+impl<Ctx> HandlerInCtx<Ctx> for Replay {
+    fn handle_in_ctx(
+        &self,
+        _: Ctx,
+        command: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, HandleError> {
+        let to_error = |e: Error| HandleError::Nfc(Box::new(e));
+
+        let mut exchanges = lock(&self.exchanges);
+        let exchange = exchanges
+            .front()
+            .ok_or_else(|| to_error(Error::UnexpectedCommand(encode(command))))?;
+
+        if !exchange.matches(command) {
+            return Err(to_error(Error::CommandMismatch {
+                expected: encode_redacted(&exchange.command),
+                actual: encode(command),
+            }));
+        }
+
+        let len = exchange.response.len();
+        if response.len() < len {
+            return Err(HandleError::NotEnoughBuffer(len));
+        }
+
+        response[..len].copy_from_slice(&exchange.response);
+        exchanges.pop_front();
+
+        Ok(len)
+    }
+}
+
+fn encode(buf: &[u8]) -> String {
+    buf.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{:02X}", b);
+        s
+    })
+}
+
+fn encode_redacted(buf: &[Option<u8>]) -> String {
+    buf.iter()
+        .map(|b| match b {
+            Some(b) => format!("{:02X}", b),
+            None => REDACTED.to_string(),
+        })
+        .collect()
+}
+
+fn decode(hex: &str) -> Option<Vec<Option<u8>>> {
+    let hex: Vec<char> = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    let pairs = hex.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+
+    pairs
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            match pair.eq_ignore_ascii_case(REDACTED) {
+                true => Some(None),
+                _ => u8::from_str_radix(&pair, 16).ok().map(Some),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::ap::crypto::CertType;
+    use crate::ap::surface::Pin;
+    use crate::ap::{CryptoAp, SupportAp, SurfaceAp};
+    use crate::secret::SecretPin;
+    use crate::Card;
+
+    const TRANSCRIPT: &str = r#"
+# Opening Support AP
+> 00A4040C0AD3921000310001010408
+< 9000
+# Verifying the PIN, that is redacted
+> 00A4020C020011
+< 9000
+> 00200080XXXXXXXXXX
+< 9000
+# Reading My Number
+> 00A4020C020001
+< 9000
+> 00B0000011
+< FF400C3132333435363738393031329000
+"#;
+
+    const CRYPTO_TRANSCRIPT: &str = r#"
+# Opening Crypto AP
+> 00A4040C0AD392F000260100000001
+< 9000
+# Reading the certificate for authentication, from the header to the size
+> 00A4020C02000A
+< 9000
+> 00B0000007
+< 3082000B3009A09000
+> 00B000000F
+< 3082000B3009A003020102020212349000
+# Signing with the PIN, that is redacted
+> 00A4020C02001B
+< 9000
+> 0020008006XXXXXXXXXXXX
+< 9000
+> 00A4020C02001A
+< 9000
+> 802A008008010203040506070800
+< ABCDEF9000
+"#;
+
+    const SURFACE_TRANSCRIPT: &str = r#"
+# Opening Surface AP
+> 00A4040C0AD3921000310001010402
+< 9000
+# Verifying the PIN type A, that is redacted
+> 00A4020C020013
+< 9000
+> 002000800CXXXXXXXXXXXXXXXXXXXXXXXX
+< 9000
+# Reading the surface, from the header to the size
+> 00A4020C020002
+< 9000
+> 00B0000007
+< 3028DF210101DF9000
+> 00B000002A
+< 3028DF210101DF220102DF230103DF240104DF250105DF260106DF270107DF280108DF290109DF2A010A9000
+"#;
+
+    #[test]
+    fn test_replay() {
+        let replay = Replay::parse(TRANSCRIPT).unwrap();
+        let card = Rc::new(Card::new(Box::new(replay)));
+        let support_ap = SupportAp::open((), Rc::clone(&card)).unwrap();
+
+        assert_eq!(
+            "123456789012",
+            support_ap
+                .read_my_number((), SecretPin::new(b"1234".to_vec()))
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_replay_crypto() {
+        let replay = Replay::parse(CRYPTO_TRANSCRIPT).unwrap();
+        let card = Rc::new(Card::new(Box::new(replay)));
+        let crypto_ap = CryptoAp::open((), Rc::clone(&card)).unwrap();
+
+        assert_eq!(
+            vec![
+                0x30, 0x82, 0x00, 0x0B, 0x30, 0x09, 0xA0, 0x03, 0x02, 0x01, 0x02, 0x02, 0x02, 0x12,
+                0x34,
+            ],
+            crypto_ap
+                .read_certificate((), CertType::Auth, SecretPin::default())
+                .unwrap(),
+        );
+        assert_eq!(
+            vec![0xAB, 0xCD, 0xEF],
+            crypto_ap
+                .sign(
+                    (),
+                    SecretPin::new(b"ABC123".to_vec()),
+                    vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08],
+                )
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_replay_surface() {
+        let replay = Replay::parse(SURFACE_TRANSCRIPT).unwrap();
+        let card = Rc::new(Card::new(Box::new(replay)));
+        let surface_ap = SurfaceAp::open((), Rc::clone(&card)).unwrap();
+
+        let surface = surface_ap
+            .read_surface((), Pin::A(SecretPin::new(b"123456789012".to_vec())))
+            .unwrap();
+        assert_eq!(vec![0x02], surface.date_of_birth);
+        assert_eq!(vec![0x05], surface.name);
+        assert_eq!(vec![0x0A], surface.code);
+    }
+
+    #[test]
+    fn test_record() {
+        let replay = Replay::parse(TRANSCRIPT).unwrap();
+        let recorder = Recorder::new(replay, vec![]);
+        let mut response = [0u8; 2];
+
+        for command in [
+            "00A4040C0AD3921000310001010408",
+            "00A4020C020011",
+            "002000800431323334",
+        ] {
+            let result = recorder.handle_in_ctx((), &decode_plain(command), &mut response);
+            assert!(matches!(result, Ok(2)));
+            assert_eq!([0x90, 0x00], response);
+        }
+
+        let (replay, transcript) = recorder.into_inner().unwrap();
+        assert_eq!(2, replay.remaining());
+        assert_eq!(
+            concat!(
+                "> 00A4040C0AD3921000310001010408\n< 9000\n",
+                "> 00A4020C020011\n< 9000\n",
+                "> 0020008004XXXXXXXX\n< 9000\n",
+            ),
+            String::from_utf8(transcript).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_record_personal() {
+        let replay = Replay::parse(TRANSCRIPT).unwrap();
+        let recorder = Recorder::new(replay, vec![]);
+        let mut response = [0u8; 32];
+
+        for command in [
+            "00A4040C0AD3921000310001010408",
+            "00A4020C020011",
+            "002000800431323334",
+            "00A4020C020001",
+            "00B0000011",
+        ] {
+            let result = recorder.handle_in_ctx((), &decode_plain(command), &mut response);
+            assert!(result.is_ok());
+        }
+
+        let (replay, transcript) = recorder.into_inner().unwrap();
+        let transcript = String::from_utf8(transcript).unwrap();
+        assert_eq!(0, replay.remaining());
+        assert!(transcript.ends_with("> 00B0000011\n< 9000\n"));
+        assert!(!transcript.contains("313233"));
+    }
+
+    #[test]
+    fn test_replay_mismatch() {
+        let replay = Replay::parse(TRANSCRIPT).unwrap();
+        let result = replay.handle_in_ctx((), &[0x00, 0xA4, 0x04, 0x0C], &mut [0u8; 2]);
+
+        let Err(HandleError::Nfc(e)) = result else {
+            panic!("The command must not match");
+        };
+        assert_eq!(
+            "Command mismatch: expected 00A4040C0AD3921000310001010408, got 00A4040C",
+            e.to_string(),
+        );
+        assert_eq!(5, replay.remaining());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(matches!(
+            Replay::parse("> 00A4\n> 00A4\n< 9000"),
+            Err(Error::MissingResponse(1)),
+        ));
+        assert!(matches!(
+            Replay::parse("< 9000"),
+            Err(Error::UnexpectedResponse(1)),
+        ));
+        assert!(matches!(
+            Replay::parse("> 0ZA4"),
+            Err(Error::InvalidLine(1)),
+        ));
+    }
+
+    fn decode_plain(hex: &str) -> Vec<u8> {
+        decode(hex)
+            .unwrap()
+            .into_iter()
+            .map(Option::unwrap)
+            .collect()
+    }
+}
@@ -12,6 +12,8 @@ use jni::JNIEnv;
 
 use jpki::ap::crypto::CertType;
 use jpki::ap::CryptoAp;
+use jpki::digest_info::DigestPolicy;
+use jpki::secret::SecretPin;
 use jpki::{card, nfc, Card};
 
 const NULL: jobject = 0 as jobject;
@@ -181,7 +183,7 @@ pub unsafe extern "C" fn Java_jp_s6n_jpki_app_ffi_LibJpki_newCard(
     let nfc_card = Box::from_raw(delegate as *mut JniNfcCard);
     let card = Card::new(nfc_card);
 
-    Box::into_raw(Box::new(card)) as jlong
+    Rc::into_raw(Rc::new(card)) as jlong
 }
 
 #[no_mangle]
@@ -192,13 +194,36 @@ pub unsafe extern "C" fn Java_jp_s6n_jpki_app_ffi_LibJpki_newCryptoAp(
 ) -> jlong {
     wrap!(jlong, {
         let ctx = JniContext { env };
-        let card = Rc::from_raw(delegate as *mut Card<JniNfcCard, JniContext>);
+        let card = delegate as *const Card<JniNfcCard, JniContext>;
+        // The card is still owned by the caller, so the AP holds another reference.
+        Rc::increment_strong_count(card);
+        let card = Rc::from_raw(card);
+        // Any digest is signed as is, not to break existing callers passing raw hashes.
         let ap = CryptoAp::open(ctx, card)?;
 
         Ok(Box::into_raw(Box::new(ap)) as jlong)
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn Java_jp_s6n_jpki_app_ffi_LibJpki_newCryptoApStrict(
+    env: JNIEnv,
+    _class: JClass,
+    delegate: jlong,
+) -> jlong {
+    wrap!(jlong, {
+        let ctx = JniContext { env };
+        let card = delegate as *const Card<JniNfcCard, JniContext>;
+        // The card is still owned by the caller, so the AP holds another reference.
+        Rc::increment_strong_count(card);
+        let card = Rc::from_raw(card);
+        // Only DigestInfo of SHA-2 family is signed, not to be used as a signing oracle.
+        let ap = CryptoAp::open(ctx, card)?.with_strict_mode(DigestPolicy::default());
+
+        Ok(Box::into_raw(Box::new(ap)) as jlong)
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn Java_jp_s6n_jpki_app_ffi_LibJpki_cryptoApReadCertificateSign(
     env: JNIEnv,
@@ -209,7 +234,7 @@ pub unsafe extern "C" fn Java_jp_s6n_jpki_app_ffi_LibJpki_cryptoApReadCertificat
 ) -> jobject {
     wrap!(jobject, {
         let ctx = JniContext { env };
-        let pin = jstring_to_bytes_vec(env, pin)?;
+        let pin = jstring_to_pin(env, pin)?;
         let ty = match ca {
             JNI_TRUE => CertType::SignCA,
             _ => CertType::Sign,
@@ -234,7 +259,7 @@ pub unsafe extern "C" fn Java_jp_s6n_jpki_app_ffi_LibJpki_cryptoApReadCertificat
 ) -> jobject {
     wrap!(jobject, {
         let ctx = JniContext { env };
-        let pin = vec![];
+        let pin = SecretPin::default();
         let ty = match ca {
             JNI_TRUE => CertType::AuthCA,
             _ => CertType::Auth,
@@ -260,7 +285,7 @@ pub unsafe extern "C" fn Java_jp_s6n_jpki_app_ffi_LibJpki_cryptoApAuth(
 ) -> jobject {
     wrap!(jobject, {
         let ctx = JniContext { env };
-        let pin = jstring_to_bytes_vec(env, pin)?;
+        let pin = jstring_to_pin(env, pin)?;
         let digest = env.convert_byte_array(digest).map_err(Error::Jni)?;
 
         let ap = &mut *(crypto_ap as *mut CryptoAp<JniNfcCard, JniContext>);
@@ -282,10 +307,11 @@ pub unsafe extern "C" fn Java_jp_s6n_jpki_app_ffi_LibJpki_cryptoApClose(
     let _ = Box::from_raw(crypto_ap as *mut CryptoAp<JniNfcCard, JniContext>);
 }
 
-fn jstring_to_bytes_vec(env: JNIEnv, str: jstring) -> Result<Vec<u8>, Error> {
+fn jstring_to_pin(env: JNIEnv, str: jstring) -> Result<SecretPin, Error> {
     Ok(env
         .get_string(unsafe { JString::from_raw(str) })
         .map_err(Error::Jni)?
         .to_bytes()
-        .to_vec())
+        .to_vec()
+        .into())
 }
@@ -2,12 +2,14 @@
 #![allow(clippy::missing_safety_doc)]
 
 use jpki::ap::crypto::CertType;
-use jpki::ap::CryptoAp;
+use jpki::ap::SharedCryptoAp;
+use jpki::digest_info::DigestPolicy;
 use jpki::nfc::{HandleError, HandlerInCtx, Result as NfcResult};
+use jpki::secret::SecretPin;
 use jpki::Card;
 use std::ffi::{c_char, CStr, CString};
 use std::ptr::null_mut;
-use std::rc::Rc;
+use std::sync::Arc;
 
 static mut LAST_ERROR: Option<String> = None;
 
@@ -131,37 +133,60 @@ pub unsafe extern "C" fn jpki_nfc_card_close(card: &mut NfcCard) {
 
 /// Creates a new card from the NFC card.
 /// This is an abstraction layer to support other protocols rather than NFC in the future.
+/// The NFC card is moved into the card, so it must not be closed after.
 #[no_mangle]
 pub unsafe extern "C" fn jpki_new_card(nfc_card: *mut NfcCard) -> *mut Card<NfcCard, ()> {
     let nfc_card = Box::from_raw(nfc_card);
     let card = Card::new(nfc_card);
 
-    Box::into_raw(Box::new(card))
+    Arc::into_raw(Arc::new(card)) as *mut _
 }
 
 /// Closes the card.
+/// APs opened on the card are still available until closed.
 #[no_mangle]
-pub unsafe extern "C" fn jpki_card_close(card: &mut Card<NfcCard, ()>) {
-    // HACK: To avoid NfcCard to be deallocated recursively, using Rc instead of Box here.
-    let _ = Rc::from_raw(card);
+pub unsafe extern "C" fn jpki_card_close(card: *mut Card<NfcCard, ()>) {
+    let _ = Arc::from_raw(card);
 }
 
 /// Opens JPKI application on the card.
+/// Any digest is signed as is, as existing callers may pass other than DigestInfo, such as raw
+/// hashes; the strict mode would break them on upgrading. Prefer `jpki_new_crypto_ap_strict`.
 #[no_mangle]
 pub unsafe extern "C" fn jpki_new_crypto_ap(
     card: *mut Card<NfcCard, ()>,
-) -> *mut CryptoAp<NfcCard, ()> {
-    let card = Rc::from_raw(card);
+) -> *mut SharedCryptoAp<NfcCard, ()> {
+    // The card is still owned by the caller, so the AP holds another reference.
+    Arc::increment_strong_count(card);
+    let card = Arc::from_raw(card);
+
+    unwrap_or(
+        SharedCryptoAp::open_shared((), card).map(|ap| Box::into_raw(Box::new(ap))),
+        null_mut(),
+    )
+}
+
+/// Opens JPKI application on the card, in the strict mode.
+/// Only DigestInfo of SHA-2 family is signed, not to be used as a signing oracle.
+#[no_mangle]
+pub unsafe extern "C" fn jpki_new_crypto_ap_strict(
+    card: *mut Card<NfcCard, ()>,
+) -> *mut SharedCryptoAp<NfcCard, ()> {
+    // The card is still owned by the caller, so the AP holds another reference.
+    Arc::increment_strong_count(card);
+    let card = Arc::from_raw(card);
 
     unwrap_or(
-        CryptoAp::open((), card).map(|ap| Box::into_raw(Box::new(ap))),
+        SharedCryptoAp::open_shared((), card)
+            .map(|ap| ap.with_strict_mode(DigestPolicy::default()))
+            .map(|ap| Box::into_raw(Box::new(ap))),
         null_mut(),
     )
 }
 
 /// Closes the opened JPKI application.
 #[no_mangle]
-pub unsafe extern "C" fn jpki_crypto_ap_close(crypto_ap: *mut CryptoAp<NfcCard, ()>) {
+pub unsafe extern "C" fn jpki_crypto_ap_close(crypto_ap: *mut SharedCryptoAp<NfcCard, ()>) {
     let _ = Box::from_raw(crypto_ap);
 }
 
@@ -170,11 +195,11 @@ pub unsafe extern "C" fn jpki_crypto_ap_close(crypto_ap: *mut CryptoAp<NfcCard,
 /// If ca is true, reads a CA certificate instead.
 #[no_mangle]
 pub unsafe extern "C" fn jpki_crypto_ap_read_certificate_sign(
-    crypto_ap: *mut CryptoAp<NfcCard, ()>,
+    crypto_ap: *mut SharedCryptoAp<NfcCard, ()>,
     pin: *const c_char,
     ca: bool,
 ) -> ByteArray {
-    let pin = CStr::from_ptr(pin).to_bytes().to_vec();
+    let pin = SecretPin::new(CStr::from_ptr(pin).to_bytes().to_vec());
     let ty = match ca {
         true => CertType::SignCA,
         _ => CertType::Sign,
@@ -193,7 +218,7 @@ pub unsafe extern "C" fn jpki_crypto_ap_read_certificate_sign(
 /// If ca is true, reads a CA certificate instead.
 #[no_mangle]
 pub unsafe extern "C" fn jpki_crypto_ap_read_certificate_auth(
-    crypto_ap: *mut CryptoAp<NfcCard, ()>,
+    crypto_ap: *mut SharedCryptoAp<NfcCard, ()>,
     ca: bool,
 ) -> ByteArray {
     let ty = match ca {
@@ -205,19 +230,20 @@ pub unsafe extern "C" fn jpki_crypto_ap_read_certificate_auth(
         crypto_ap
             .as_ref()
             .unwrap()
-            .read_certificate((), ty, vec![])
+            .read_certificate((), ty, SecretPin::default())
             .map(|v| v.into()),
     )
 }
 
 /// Sign the computed digest using the key-pair for user authentication.
+/// The digest must be a DER-encoded DigestInfo in the strict mode.
 #[no_mangle]
 pub unsafe extern "C" fn jpki_crypto_ap_auth(
-    crypto_ap: *mut CryptoAp<NfcCard, ()>,
+    crypto_ap: *mut SharedCryptoAp<NfcCard, ()>,
     pin: *const c_char,
     digest: ByteArray,
 ) -> ByteArray {
-    let pin = CStr::from_ptr(pin).to_bytes().to_vec();
+    let pin = SecretPin::new(CStr::from_ptr(pin).to_bytes().to_vec());
 
     unwrap(
         crypto_ap
@@ -229,13 +255,14 @@ pub unsafe extern "C" fn jpki_crypto_ap_auth(
 }
 
 /// Sign the computed digest using the key-pair for signing.
+/// The digest must be a DER-encoded DigestInfo in the strict mode.
 #[no_mangle]
 pub unsafe extern "C" fn jpki_crypto_ap_sign(
-    crypto_ap: *mut CryptoAp<NfcCard, ()>,
+    crypto_ap: *mut SharedCryptoAp<NfcCard, ()>,
     pin: *const c_char,
     digest: ByteArray,
 ) -> ByteArray {
-    let pin = CStr::from_ptr(pin).to_bytes().to_vec();
+    let pin = SecretPin::new(CStr::from_ptr(pin).to_bytes().to_vec());
 
     unwrap(
         crypto_ap